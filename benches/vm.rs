@@ -0,0 +1,42 @@
+//! Speed of the VM on the whole solution: Runs all the maze commands, with and without the
+//! cache of decoded instructions. Without the cache, each step decodes the instruction again,
+//! into the same enum.
+//!
+//! Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use synacor_challenge::maze::maze_commands::COMMANDS;
+use synacor_challenge::maze::patch_code;
+use synacor_challenge::maze::teleporter_code::find_teleporter_code;
+use synacor_challenge::vm::program::Program;
+use synacor_challenge::vm::Vm;
+
+// Runs the commands like the codes command does, patching the teleporter when reaching it.
+fn run_commands(program: &Program, teleporter_code: u16, cache: bool) -> usize {
+    let mut vm = Vm::new(program);
+    vm.storage_mut().mem.set_instruction_cache(cache);
+    for command in COMMANDS {
+        if command.trim() == "look strange book" {
+            patch_code::patch_with_code(vm.storage_mut(), teleporter_code);
+        }
+        vm.run_actions(&[command]);
+    }
+    vm.take_output().len()
+}
+
+fn bench_commands(c: &mut Criterion) {
+    let program = Program::challenge().unwrap();
+    // Searched once, as it takes longer than running the commands.
+    let code = find_teleporter_code().unwrap();
+    let mut group = c.benchmark_group("commands");
+    group.sample_size(10);
+    group.bench_function("cached", |b| b.iter(|| run_commands(&program, code, true)));
+    group.bench_function("uncached", |b| {
+        b.iter(|| run_commands(&program, code, false))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_commands);
+criterion_main!(benches);
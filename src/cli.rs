@@ -0,0 +1,456 @@
+//! Command line parsing.
+
+use std::fmt;
+
+use synacor_challenge::maze::planner::Target;
+use synacor_challenge::vm::tracer::TraceFilter;
+
+// History kept by default when starting in the debugger.
+const DEFAULT_HISTORY: usize = 100_000;
+
+pub const USAGE: &str = r"Usage: synacor_challenge [options] [command]
+
+Commands:
+    run [file]      Run the program (default), first replaying the commands in <file>, one per line.
+    debug [file]    Same as run, but start in the debugger.
+    disasm [file]   Decompile the binary, following its control flow. With --traced, also follow
+                    the indirect jumps reached when replaying the commands in <file> (the
+                    solution commands by default).
+    asm <src> <out> Assemble the source, in the syntax of disasm, into the binary <out>.
+    strings         Print the game text with its address, decrypting it without running the game.
+    solve <puzzle>  Solve one of the puzzles: coins, teleporter, orb.
+    explore [file]  Map the rooms reachable by taking exits, after replaying the commands in <file>.
+    plan <kind> <name>
+                    Print the shortest commands to reach a room, take an item or show a code,
+                    with <kind> room, item or code (the code given by its MD5).
+    codes           Run the solution commands, then print the codes found.
+
+Options:
+    --bin <path>        Binary to load (default resources/challenge.bin).
+    --history <n>       Instructions to keep for stepping backwards in the debugger (default 100000
+                        with debug, 0 otherwise).
+    --load-state <file> Resume from a state saved with the debugger `save` command.
+    --patch-teleporter  Patch the teleporter check when the replayed commands reach it.
+    --verbose           Print each executed instruction.
+    --trace <file>      Write each executed instruction to <file>, as JSON lines.
+    --trace-range <a-b> Only trace instructions at addresses <a> to <b>.
+    --trace-from <n>    Only trace from the <n>th executed instruction.
+    --trace-limit <n>   Trace at most <n> instructions.
+    --profile           Print the most executed addresses and functions at exit.
+    --profile-folded <file>
+                        Also write the call stacks of the profile to <file>, for flamegraph tools.
+    --format <fmt>      Format of the explore map: text (default), dot or json.
+    --traced            Run the program when decompiling, see disasm.
+    -h, --help          Show this help.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Puzzle {
+    Coins,
+    Teleporter,
+    Orb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    Text,
+    Dot,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run {
+        replay: Option<String>,
+    },
+    Debug {
+        replay: Option<String>,
+    },
+    Disasm {
+        traced: bool,
+        replay: Option<String>,
+    },
+    Asm {
+        source: String,
+        output: String,
+    },
+    Strings,
+    Solve(Puzzle),
+    Explore {
+        replay: Option<String>,
+    },
+    Plan(Target),
+    Codes,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub bin: Option<String>,
+    pub load_state: Option<String>,
+    // Explicit history, see `history()` for the default.
+    pub history: Option<usize>,
+    pub patch_teleporter: bool,
+    pub verbose: bool,
+    pub trace: Option<String>,
+    pub trace_filter: TraceFilter,
+    pub profile: bool,
+    pub profile_folded: Option<String>,
+    pub map_format: MapFormat,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Cli {
+    // Parses the arguments, without the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, CliError> {
+        let mut bin = None;
+        let mut load_state = None;
+        let mut history = None;
+        let mut patch_teleporter = false;
+        let mut verbose = false;
+        let mut trace = None;
+        let mut trace_filter = TraceFilter::default();
+        let mut profile = false;
+        let mut profile_folded = None;
+        let mut map_format = MapFormat::Text;
+        let mut traced = false;
+        let mut positional: Vec<String> = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bin" => {
+                    bin = Some(
+                        args.next()
+                            .ok_or_else(|| CliError("--bin needs a path".to_string()))?,
+                    );
+                }
+                "--load-state" => {
+                    load_state = Some(
+                        args.next()
+                            .ok_or_else(|| CliError("--load-state needs a path".to_string()))?,
+                    );
+                }
+                "--history" => {
+                    history = Some(
+                        args.next()
+                            .and_then(|n| n.parse().ok())
+                            .ok_or_else(|| CliError("--history needs a number".to_string()))?,
+                    );
+                }
+                "--trace" => {
+                    trace = Some(
+                        args.next()
+                            .ok_or_else(|| CliError("--trace needs a path".to_string()))?,
+                    );
+                }
+                "--trace-range" => {
+                    trace_filter.range = Some(
+                        args.next()
+                            .and_then(|r| TraceFilter::parse_range(&r))
+                            .ok_or_else(|| {
+                                CliError("--trace-range needs a range like 6049-6089".to_string())
+                            })?,
+                    );
+                }
+                "--trace-from" => {
+                    trace_filter.from_step = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or_else(|| CliError("--trace-from needs a number".to_string()))?;
+                }
+                "--trace-limit" => {
+                    trace_filter.limit = Some(
+                        args.next()
+                            .and_then(|n| n.parse().ok())
+                            .ok_or_else(|| CliError("--trace-limit needs a number".to_string()))?,
+                    );
+                }
+                "--profile" => profile = true,
+                "--profile-folded" => {
+                    profile = true;
+                    profile_folded =
+                        Some(args.next().ok_or_else(|| {
+                            CliError("--profile-folded needs a path".to_string())
+                        })?);
+                }
+                "--format" => {
+                    map_format = match args.next().as_deref() {
+                        Some("text") => MapFormat::Text,
+                        Some("dot") => MapFormat::Dot,
+                        Some("json") => MapFormat::Json,
+                        _ => return Err(CliError("--format needs text, dot or json".to_string())),
+                    };
+                }
+                "--patch-teleporter" => patch_teleporter = true,
+                "--verbose" => verbose = true,
+                "--traced" => traced = true,
+                "-h" | "--help" => positional.insert(0, "help".to_string()),
+                _ if arg.starts_with('-') => {
+                    return Err(CliError(format!("Unknown option {}", arg)));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        let command = match positional.first().map(String::as_str) {
+            None => Command::Run { replay: None },
+            Some("codes") => Command::Codes,
+            Some("run") => Command::Run {
+                replay: positional.get(1).cloned(),
+            },
+            Some("debug") => Command::Debug {
+                replay: positional.get(1).cloned(),
+            },
+            Some("disasm") => match positional.get(1) {
+                Some(_) if !traced => {
+                    return Err(CliError(
+                        "disasm replays a file only with --traced".to_string(),
+                    ))
+                }
+                replay => Command::Disasm {
+                    traced,
+                    replay: replay.cloned(),
+                },
+            },
+            Some("strings") => Command::Strings,
+            Some("asm") => match (positional.get(1), positional.get(2)) {
+                (Some(source), Some(output)) => Command::Asm {
+                    source: source.clone(),
+                    output: output.clone(),
+                },
+                _ => return Err(CliError("asm needs a source and an output".to_string())),
+            },
+            Some("solve") => Command::Solve(match positional.get(1).map(String::as_str) {
+                Some("coins") => Puzzle::Coins,
+                Some("teleporter") => Puzzle::Teleporter,
+                Some("orb") => Puzzle::Orb,
+                Some(p) => return Err(CliError(format!("Unknown puzzle {}", p))),
+                None => return Err(CliError("solve needs a puzzle".to_string())),
+            }),
+            Some("explore") => Command::Explore {
+                replay: positional.get(1).cloned(),
+            },
+            Some("plan") => {
+                let name = positional[2.min(positional.len())..].join(" ");
+                if name.is_empty() {
+                    return Err(CliError("plan needs a kind and a name".to_string()));
+                }
+                Command::Plan(match positional.get(1).map(String::as_str) {
+                    Some("room") => Target::Room(name),
+                    Some("item") => Target::Item(name),
+                    Some("code") => Target::Code(name),
+                    _ => return Err(CliError("plan needs room, item or code".to_string())),
+                })
+            }
+            Some("help") => Command::Help,
+            Some(c) => return Err(CliError(format!("Unknown command {}", c))),
+        };
+
+        Ok(Self {
+            command,
+            bin,
+            load_state,
+            history,
+            patch_teleporter,
+            verbose,
+            trace,
+            trace_filter,
+            profile,
+            profile_folded,
+            map_format,
+        })
+    }
+
+    // Instructions to keep for stepping backwards: Recording them slows the VM down, so it's
+    // only done by default when starting in the debugger.
+    pub fn history(&self) -> usize {
+        match (self.history, &self.command) {
+            (Some(n), _) => n,
+            (None, Command::Debug { .. }) => DEFAULT_HISTORY,
+            (None, _) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, CliError> {
+        Cli::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_default() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.command, Command::Run { replay: None });
+        assert_eq!(parse(&["codes"]).unwrap().command, Command::Codes);
+        assert_eq!(cli.bin, None);
+        assert_eq!(cli.load_state, None);
+        assert_eq!(cli.history(), 0);
+        assert_eq!(parse(&["debug"]).unwrap().history(), DEFAULT_HISTORY);
+        assert!(!cli.patch_teleporter);
+        assert!(!cli.verbose);
+        assert_eq!(cli.trace, None);
+        assert_eq!(cli.trace_filter, TraceFilter::default());
+        assert!(!cli.profile);
+    }
+
+    #[test]
+    fn test_run_with_options() {
+        let cli = parse(&[
+            "--bin",
+            "patched.bin",
+            "run",
+            "cmds.txt",
+            "--load-state",
+            "vault.state",
+            "--history",
+            "0",
+            "--patch-teleporter",
+            "--verbose",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Run {
+                replay: Some("cmds.txt".to_string())
+            }
+        );
+        assert_eq!(cli.bin, Some("patched.bin".to_string()));
+        assert_eq!(cli.load_state, Some("vault.state".to_string()));
+        assert_eq!(cli.history(), 0);
+        assert!(cli.patch_teleporter);
+        assert!(cli.verbose);
+        assert_eq!(parse(&["run", "--history", "50"]).unwrap().history(), 50);
+    }
+
+    #[test]
+    fn test_trace() {
+        let cli = parse(&[
+            "run",
+            "--trace",
+            "run.trace",
+            "--trace-range",
+            "6049-6089",
+            "--trace-from",
+            "1000",
+            "--trace-limit",
+            "50",
+        ])
+        .unwrap();
+        assert_eq!(cli.trace, Some("run.trace".to_string()));
+        assert_eq!(
+            cli.trace_filter,
+            TraceFilter {
+                range: Some((6049, 6089)),
+                from_step: 1000,
+                limit: Some(50),
+            }
+        );
+        assert!(parse(&["--trace-range", "6049"]).is_err());
+        assert!(parse(&["--trace-limit"]).is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let cli = parse(&["--profile"]).unwrap();
+        assert!(cli.profile);
+        assert_eq!(cli.profile_folded, None);
+
+        let cli = parse(&["--profile-folded", "codes.folded"]).unwrap();
+        assert!(cli.profile);
+        assert_eq!(cli.profile_folded, Some("codes.folded".to_string()));
+        assert!(parse(&["--profile-folded"]).is_err());
+    }
+
+    #[test]
+    fn test_solve() {
+        assert_eq!(
+            parse(&["solve", "orb"]).unwrap().command,
+            Command::Solve(Puzzle::Orb)
+        );
+        assert!(parse(&["solve"]).is_err());
+        assert!(parse(&["solve", "maze"]).is_err());
+    }
+
+    #[test]
+    fn test_explore() {
+        assert_eq!(
+            parse(&["explore"]).unwrap().command,
+            Command::Explore { replay: None }
+        );
+        assert_eq!(
+            parse(&["explore", "commands.txt"]).unwrap().command,
+            Command::Explore {
+                replay: Some("commands.txt".to_string())
+            }
+        );
+        assert_eq!(parse(&["explore"]).unwrap().map_format, MapFormat::Text);
+        assert_eq!(
+            parse(&["--format", "dot", "explore"]).unwrap().map_format,
+            MapFormat::Dot
+        );
+        assert!(parse(&["--format", "svg"]).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            parse(&["plan", "item", "lit", "lantern"]).unwrap().command,
+            Command::Plan(Target::Item("lit lantern".to_string()))
+        );
+        assert!(parse(&["plan", "room"]).is_err());
+        assert!(parse(&["plan", "place", "Ruins"]).is_err());
+    }
+
+    #[test]
+    fn test_disasm() {
+        assert_eq!(
+            parse(&["disasm"]).unwrap().command,
+            Command::Disasm {
+                traced: false,
+                replay: None
+            }
+        );
+        assert_eq!(
+            parse(&["disasm", "--traced", "cmds.txt"]).unwrap().command,
+            Command::Disasm {
+                traced: true,
+                replay: Some("cmds.txt".to_string())
+            }
+        );
+        assert!(parse(&["disasm", "cmds.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_asm() {
+        assert_eq!(
+            parse(&["asm", "patch.asm", "patch.bin"]).unwrap().command,
+            Command::Asm {
+                source: "patch.asm".to_string(),
+                output: "patch.bin".to_string()
+            }
+        );
+        assert!(parse(&["asm", "patch.asm"]).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["--bin"]).is_err());
+        assert!(parse(&["run", "--load-state"]).is_err());
+        assert!(parse(&["--history", "many"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["play"]).is_err());
+    }
+}
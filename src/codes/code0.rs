@@ -1,10 +1,10 @@
 #![cfg(test)]
 
-use crate::codes::codes_check::verify_code;
+use crate::codes::codes_check::{verify_code, SPEC_CODE};
 
 fn code() -> String {
     // First code was in the spec.
-    "LDOb7UGhTi".to_string()
+    SPEC_CODE.to_string()
 }
 
 #[test]
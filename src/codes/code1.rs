@@ -1,14 +1,14 @@
 #![cfg(test)]
 
-use regex::Regex;
-
 use crate::codes::codes_check::verify_code;
+use crate::maze::parser;
 use crate::vm::run;
 
 fn code() -> String {
     let msg = run::execute_actions(&[]);
-    let re = Regex::new(r"into the challenge website: (\w+)").unwrap();
-    re.captures(&msg).unwrap()[1].to_string()
+    let events = parser::parse(None, &msg);
+    let code = parser::codes(&events).next().unwrap();
+    code.to_string()
 }
 
 #[test]
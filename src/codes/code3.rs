@@ -1,16 +1,15 @@
 #![cfg(test)]
 
-use regex::Regex;
-
 use crate::codes::codes_check::verify_code;
+use crate::maze::parser;
 use crate::vm::run;
 
 fn code() -> String {
     let actions = ["take tablet", "use tablet"];
 
     let msg = run::execute_actions(&actions);
-    let re = Regex::new(r#"You find yourself writing \"(\w+)\" on the tablet"#).unwrap();
-    re.captures(&msg).unwrap()[1].to_string()
+    let events = parser::parse(None, &msg);
+    parser::codes(&events).last().unwrap().to_string()
 }
 
 #[test]
@@ -1,18 +1,16 @@
 #![cfg(test)]
 
-use regex::Regex;
-
 use crate::codes::codes_check::verify_code;
 use crate::maze;
+use crate::maze::parser;
 use crate::vm::run;
 
 fn code() -> String {
     let actions = &maze::maze_commands::COMMANDS[0..=50];
 
     let msg: String = run::execute_actions(actions);
-    let re = Regex::new(r"You activate the teleporter!  As you spiral through time and space, you think you see a pattern in the stars\.\.\.[.\n ]+(\w+)")
-        .unwrap();
-    re.captures(&msg).unwrap()[1].to_string()
+    let events = parser::parse(None, &msg);
+    parser::codes(&events).last().unwrap().to_string()
 }
 
 #[test]
@@ -1,35 +1,28 @@
 #![cfg(test)]
 
-use regex::Regex;
-
 use crate::codes::codes_check::verify_code;
+use crate::codes::teleporter_code;
 use crate::maze;
+use crate::maze::parser;
 use crate::maze::patch_code;
-use crate::vm::run;
-use crate::vm::storage::Storage;
-use crate::vm::terminal::Terminal;
+use crate::vm::program::Program;
+use crate::vm::Vm;
 
 fn code() -> String {
-    let mut storage = Storage::new();
-    let mut ir: u16 = 0;
-    let mut terminal = Terminal::new(false);
+    let mut vm = Vm::new(&Program::challenge().unwrap());
 
     // Run first set of actions until we find the teleporter
     let actions = &maze::maze_commands::COMMANDS[0..=51];
-    run::execute_actions_with_storage(actions, &mut ir, &mut storage, &mut terminal);
+    vm.run_actions(actions);
+    vm.take_output();
 
     // Patch the program with the correct code and to by-pass the check
-    patch_code::patch(&mut storage);
+    patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
 
-    let msg: String = run::execute_actions_with_storage(
-        &["use teleporter"],
-        &mut ir,
-        &mut storage,
-        &mut terminal,
-    );
-    let re =
-        Regex::new(r"Someone seems to have drawn a message in the sand here:[.\n ]+(\w+)").unwrap();
-    re.captures(&msg).unwrap()[1].to_string()
+    vm.run_actions(&["use teleporter"]);
+    let msg = vm.take_output();
+    let events = parser::parse(None, &msg);
+    parser::codes(&events).last().unwrap().to_string()
 }
 
 #[test]
@@ -1,53 +1,30 @@
 #![cfg(test)]
 
-use regex::Regex;
-
-use crate::codes::codes_check::verify_code;
+use crate::codes::codes_check::{code_number, verify_code};
+use crate::codes::teleporter_code;
 use crate::maze;
+use crate::maze::mirror;
+use crate::maze::parser;
 use crate::maze::patch_code;
-use crate::vm::run;
-use crate::vm::storage::Storage;
-use crate::vm::terminal::Terminal;
+use crate::vm::program::Program;
+use crate::vm::Vm;
 
 fn code() -> String {
-    let mut storage = Storage::new();
-    let mut ir: u16 = 0;
-    let mut terminal = Terminal::new(false);
+    let mut vm = Vm::new(&Program::challenge().unwrap());
 
     // Run first set of actions until we find the teleporter
-    run::execute_actions_with_storage(
-        &maze::maze_commands::COMMANDS[0..=51],
-        &mut ir,
-        &mut storage,
-        &mut terminal,
-    );
+    vm.run_actions(&maze::maze_commands::COMMANDS[0..=51]);
+    vm.take_output();
 
     // Patch the program with the correct code and to by-pass the check
-    patch_code::patch(&mut storage);
+    patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
 
-    let msg: String = run::execute_actions_with_storage(
-        &maze::maze_commands::COMMANDS[52..],
-        &mut ir,
-        &mut storage,
-        &mut terminal,
-    );
-    let re =
-        Regex::new(r#"But wait!  It looks like someone wrote on your face while you were unconscious on the beach!  Through the mirror, you see \"(\w+)\" scrawled in charcoal on your forehead."#).unwrap();
-    let code = re.captures(&msg).unwrap()[1].to_string();
+    vm.run_actions(&maze::maze_commands::COMMANDS[52..]);
+    let msg = vm.take_output();
+    let events = parser::parse(None, &msg);
+    let code = parser::codes(&events).last().unwrap();
     // Since we saw the code in the mirror, it needs to be flipped :-)
-    mirror_code(&code)
-}
-
-fn mirror_code(code: &str) -> String {
-    // It's both reversing the string, and mirror each char if needed
-    code.chars()
-        .rev()
-        .map(|c| match c {
-            'p' => 'q',
-            'q' => 'p',
-            _ => c,
-        })
-        .collect()
+    mirror::decode(code, code_number).unwrap().1
 }
 
 #[test]
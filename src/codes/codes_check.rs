@@ -1,8 +1,6 @@
-#![cfg(test)]
-
 // MD5 hashes of the 8 codes produced by the challenge.
 // echo -n "<Code Here>" | md5sum
-const CODES: [&str; 8] = [
+pub const CODES: [&str; 8] = [
     "76ec2408e8fe3f1753c25db51efd8eb3",
     "0e6aa7be1f68d930926d72b3741a145c",
     "7997a3b2941eab92c1c0345d5747b420",
@@ -13,6 +11,28 @@ const CODES: [&str; 8] = [
     "d0c54d4ed7f943280ce3e19532dbb1a6",
 ];
 
+// The first code isn't in the game, but in the arch-spec coming with it.
+pub const SPEC_CODE: &str = "LDOb7UGhTi";
+
 pub fn verify_code(code_nb: usize, code: &str) -> bool {
-    format!("{:x}", md5::compute(code.as_bytes())) == CODES[code_nb]
+    CODES.get(code_nb) == Some(&format!("{:x}", md5::compute(code.as_bytes())).as_str())
+}
+
+// Number of the code the candidate is, if any.
+pub fn code_number(candidate: &str) -> Option<usize> {
+    let hash = format!("{:x}", md5::compute(candidate.as_bytes()));
+    CODES.iter().position(|c| *c == hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_code_number() {
+        assert_eq!(code_number("LDOb7UGhTi"), Some(0));
+        assert_eq!(code_number("LDOb7UGhTI"), None);
+        assert!(!verify_code(1, "LDOb7UGhTi"));
+        assert!(!verify_code(8, "LDOb7UGhTi"));
+    }
 }
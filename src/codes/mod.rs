@@ -1,4 +1,4 @@
-#![cfg(test)]
+//! The codes of the challenge: Checking them, and the tests finding each of them.
 
 mod code0;
 mod code1;
@@ -9,4 +9,15 @@ mod code5;
 mod code6;
 mod code7;
 
-mod codes_check;
+pub mod codes_check;
+pub mod report;
+
+#[cfg(test)]
+use std::sync::OnceLock;
+
+// Code of the teleporter, searched once for all the tests needing it.
+#[cfg(test)]
+pub(crate) fn teleporter_code() -> u16 {
+    static CODE: OnceLock<u16> = OnceLock::new();
+    *CODE.get_or_init(|| crate::maze::teleporter_code::find_teleporter_code().unwrap())
+}
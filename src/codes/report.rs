@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use crate::codes::codes_check::{verify_code, CODES, SPEC_CODE};
+use crate::maze::code_scanner::FoundCode;
+use crate::maze::maze_commands::COMMANDS;
+
+// Reading the tablet gives a code, but isn't needed to go through the maze.
+const TABLET_COMMANDS: [&str; 2] = ["take tablet", "use tablet"];
+
+// Commands to run for finding all the codes of the game: The tablet, then the maze.
+pub fn commands() -> Vec<&'static str> {
+    TABLET_COMMANDS
+        .iter()
+        .chain(COMMANDS.iter())
+        .copied()
+        .collect()
+}
+
+fn found_at(found: &FoundCode, commands: &[&str]) -> String {
+    let at = match found.command {
+        Some(i) => format!(
+            "command {} ({}), step {}",
+            i,
+            commands.get(i).map_or("?", |c| c.trim()),
+            found.step
+        ),
+        None => format!("before the first command, step {}", found.step),
+    };
+    match &found.mirrored {
+        Some(seen) => format!("{}, seen in a mirror as {}", at, seen),
+        None => at,
+    }
+}
+
+// Table of the codes, with where they were found while running the commands. The code of the
+// spec can't be found in the game, so it's checked on its own.
+// Candidates that aren't known codes are listed after it.
+pub fn report(found: &[FoundCode], commands: &[&str]) -> String {
+    let mut report = format!(
+        "{:<4}  {:<8}  {:<12}  Found at\n",
+        "Code", "Status", "Value"
+    );
+    for code_nb in 0..CODES.len() {
+        match found.iter().find(|f| f.known == Some(code_nb)) {
+            Some(f) => writeln!(
+                report,
+                "{:<4}  {:<8}  {:<12}  {}",
+                code_nb,
+                "verified",
+                f.code,
+                found_at(f, commands)
+            ),
+            None if code_nb == 0 && verify_code(0, SPEC_CODE) => writeln!(
+                report,
+                "{:<4}  {:<8}  {:<12}  the arch-spec",
+                code_nb, "verified", SPEC_CODE
+            ),
+            None => writeln!(report, "{:<4}  missing", code_nb),
+        }
+        .unwrap();
+    }
+    for f in found.iter().filter(|f| f.known.is_none()) {
+        writeln!(
+            report,
+            "Unverified candidate {}, {}",
+            f.code,
+            found_at(f, commands)
+        )
+        .unwrap();
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let found = [
+            FoundCode {
+                code: "ghijklGHIJKL".to_string(),
+                known: Some(1),
+                lead_in: None,
+                mirrored: None,
+                command: None,
+                step: 10,
+            },
+            FoundCode {
+                code: "mnopqrMNOPQR".to_string(),
+                known: Some(7),
+                lead_in: None,
+                mirrored: Some("RQPONMrqponm".to_string()),
+                command: Some(1),
+                step: 15,
+            },
+            FoundCode {
+                code: "abcdefABCDEF".to_string(),
+                known: None,
+                lead_in: None,
+                mirrored: None,
+                command: Some(1),
+                step: 20,
+            },
+        ];
+        let report = report(&found, &["go north", "use mirror"]);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[1], "0     verified  LDOb7UGhTi    the arch-spec");
+        assert_eq!(lines[3], "2     missing");
+        assert_eq!(
+            lines[2],
+            "1     verified  ghijklGHIJKL  before the first command, step 10"
+        );
+        assert_eq!(
+            lines[8],
+            "7     verified  mnopqrMNOPQR  command 1 (use mirror), step 15, seen in a mirror as RQPONMrqponm"
+        );
+        assert_eq!(
+            lines[9],
+            "Unverified candidate abcdefABCDEF, command 1 (use mirror), step 20"
+        );
+    }
+}
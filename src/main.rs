@@ -1,13 +1,233 @@
-mod codes;
-mod maze;
-mod vm;
+mod cli;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::{env, fs, process};
+
+use itertools::Itertools;
+
+use synacor_challenge::codes;
+use synacor_challenge::maze;
+use synacor_challenge::maze::code_scanner::CodeScanner;
+use synacor_challenge::maze::planner::{Planner, Target};
+use synacor_challenge::vm;
+use synacor_challenge::vm::machine::{ExitReason, Vm};
+use synacor_challenge::vm::profiler::Profiler;
+use synacor_challenge::vm::program::Program;
+use synacor_challenge::vm::run::RunOptions;
+use synacor_challenge::vm::snapshot::Snapshot;
+use synacor_challenge::vm::tracer::Tracer;
+
+use cli::{Cli, Command, MapFormat, Puzzle, USAGE};
+
+fn load_program(bin: &Option<String>) -> Program {
+    let program = match bin {
+        Some(path) => Program::from_path(path),
+        None => Program::challenge(),
+    };
+    program.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+// Reads the commands to replay, one per line.
+fn load_replay(path: &Option<String>) -> Vec<String> {
+    let Some(path) = path else {
+        return Vec::new();
+    };
+    let content = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        process::exit(1);
+    });
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Reports how the program ended, failing the process on a fault.
+fn exit_with(reason: ExitReason) {
+    println!("{}", reason);
+    if let ExitReason::Fault(_) = reason {
+        process::exit(1);
+    }
+}
+
+fn load_state(path: &Option<String>) -> Option<Snapshot> {
+    path.as_ref().map(|path| {
+        Snapshot::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    })
+}
+
+fn assemble(source: &str, output: &str) {
+    let text = fs::read_to_string(source).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", source, e);
+        process::exit(1);
+    });
+    let program = vm::assembler::assemble(&text)
+        .map_err(|e| e.to_string())
+        .and_then(|words| Program::from_words(words).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", source, e);
+            process::exit(1);
+        });
+    if let Err(e) = fs::write(output, program.to_bytes()) {
+        eprintln!("Failed to write {}: {}", output, e);
+        process::exit(1);
+    }
+    println!("Wrote {} words to {}", program.len(), output);
+}
+
+// Prints the map of the rooms reachable from the saved state, once the commands are replayed.
+fn explore(program: &Program, state: Option<Snapshot>, replay: &[String], format: MapFormat) {
+    let mut vm = Vm::new(program);
+    if let Some(state) = state {
+        vm.restore(&state);
+    }
+    let actions: Vec<&str> = replay.iter().map(String::as_str).collect();
+    let reason = vm.run_actions(&actions);
+    if reason != ExitReason::InputExhausted {
+        exit_with(reason);
+        process::exit(1);
+    }
+    match maze::explorer::explore(&mut vm) {
+        Ok(map) => match format {
+            MapFormat::Text => print!("{}", map),
+            MapFormat::Dot => print!("{}", maze::map_export::to_dot(&map)),
+            MapFormat::Json => print!("{}", maze::map_export::to_json(&map)),
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Prints the commands reaching the target, one per line, so they can be replayed.
+fn plan(program: &Program, state: Option<Snapshot>, target: &Target) {
+    let mut vm = Vm::new(program);
+    if let Some(state) = state {
+        vm.restore(&state);
+    }
+    match Planner::default().plan(&mut vm, target) {
+        Ok(commands) => {
+            for command in commands {
+                println!("{}", command);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn create_tracer(cli: &Cli) -> Option<Tracer> {
+    cli.trace.as_ref().map(|path| {
+        Tracer::create(path, cli.trace_filter.clone()).unwrap_or_else(|e| {
+            eprintln!("Failed to create {}: {}", path, e);
+            process::exit(1);
+        })
+    })
+}
 
 fn main() {
-    // To decompile the binary:
-    // vm::decompiler::decompile();
+    let cli = Cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
 
-    // To run the program without saved commands:
-    // vm::run::execute_program(&[]);
+    let mut options = RunOptions {
+        patch_teleporter: cli.patch_teleporter,
+        verbose: cli.verbose,
+        debug: false,
+        load_state: load_state(&cli.load_state),
+        history: cli.history(),
+        trace: create_tracer(&cli),
+        profile: cli.profile.then(Profiler::default),
+        profile_folded: cli.profile_folded.clone(),
+        output_hook: None,
+        stop_after_actions: false,
+    };
 
-    vm::run::execute_program(&maze::maze_commands::COMMANDS);
+    match &cli.command {
+        Command::Run { replay } | Command::Debug { replay } => {
+            options.debug = matches!(cli.command, Command::Debug { .. });
+            let actions = load_replay(replay);
+            let actions: Vec<&str> = actions.iter().map(String::as_str).collect();
+            exit_with(vm::run::execute_program(
+                &load_program(&cli.bin),
+                &actions,
+                options,
+            ));
+        }
+        Command::Disasm { traced, replay } => {
+            let program = load_program(&cli.bin);
+            if *traced {
+                let lines = load_replay(replay);
+                let actions: Vec<&str> = if replay.is_some() {
+                    lines.iter().map(String::as_str).collect()
+                } else {
+                    maze::maze_commands::COMMANDS.to_vec()
+                };
+                vm::decompiler::decompile(&program, &actions);
+            } else {
+                print!("{}", vm::decompiler::disassemble(&program));
+            }
+        }
+        Command::Asm { source, output } => assemble(source, output),
+        Command::Strings => {
+            for s in vm::strings::extract_strings(&load_program(&cli.bin)) {
+                println!("{}", s);
+            }
+        }
+        Command::Solve(puzzle) => match puzzle {
+            Puzzle::Coins => println!(
+                "Coins order: {}",
+                maze::coins_order_solver::find_right_order().join(", ")
+            ),
+            Puzzle::Teleporter => match maze::teleporter_code::find_teleporter_codes(1..=32767) {
+                Ok(codes) => println!("Teleporter code: {}", codes.iter().join(", ")),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            },
+            Puzzle::Orb => match maze::orb::Vault::orb().solve() {
+                Ok(commands) => println!("Orb path: {}", commands.join(", ")),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            },
+        },
+        Command::Explore { replay } => explore(
+            &load_program(&cli.bin),
+            options.load_state.take(),
+            &load_replay(replay),
+            cli.map_format,
+        ),
+        Command::Plan(target) => plan(&load_program(&cli.bin), options.load_state.take(), target),
+        Command::Codes => {
+            // The solution commands cannot go past the teleporter without the patch.
+            options.patch_teleporter = true;
+            options.stop_after_actions = true;
+            let scanner = Rc::new(RefCell::new(CodeScanner::default()));
+            options.output_hook = Some(Box::new(scanner.clone()));
+            let commands = codes::report::commands();
+            let reason = vm::run::execute_program(&load_program(&cli.bin), &commands, options);
+            print!(
+                "\n{}",
+                codes::report::report(scanner.borrow().found(), &commands)
+            );
+            exit_with(reason);
+        }
+        Command::Help => println!("{}", USAGE),
+    }
 }
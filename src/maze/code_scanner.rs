@@ -0,0 +1,236 @@
+//! Spots the codes as the game writes them, whatever the commands run.
+//!
+//! Hooked to the terminal as its output hook, it sees each char written. A word is flagged as a code when it's a
+//! word of 12 letters and digits following a phrase introducing a code, or when its MD5 is one
+//! of the known codes. A code seen in a mirror is read back with `maze::mirror` when one of its
+//! readings is known.
+
+use crate::codes::codes_check::CODES;
+use crate::maze::mirror;
+use crate::vm::terminal::OutputHook;
+
+// How many chars of text to keep for finding the lead-ins.
+const TEXT_KEPT: usize = 128;
+
+const CODE_LEN: usize = 12;
+
+const MIRROR_LEAD_IN: &str = "Through the mirror, you see \"";
+
+// Text introducing a code, just before it, ignoring spaces.
+const LEAD_INS: [&str; 7] = [
+    "challenge website:",
+    "completion code is:",
+    "You find yourself writing \"",
+    "Chiseled on the wall of one of the passageways, you see:",
+    "you think you see a pattern in the stars...",
+    "drawn a message in the sand here:",
+    MIRROR_LEAD_IN,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundCode {
+    pub code: String,
+    // Index of the known code with the same MD5.
+    pub known: Option<usize>,
+    pub lead_in: Option<&'static str>,
+    // Text seen in the mirror, when the code was read from it.
+    pub mirrored: Option<String>,
+    // Index of the command that was running, None before the first one.
+    pub command: Option<usize>,
+    // VM step writing the first char of the code.
+    pub step: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeScanner {
+    // MD5 of the known codes, as lowercase hex.
+    hashes: Vec<String>,
+    text: String,
+    word: String,
+    word_step: u64,
+    found: Vec<FoundCode>,
+}
+
+// Scans for the codes of the challenge.
+impl Default for CodeScanner {
+    fn default() -> Self {
+        Self::new(&CODES)
+    }
+}
+
+impl CodeScanner {
+    pub fn new(hashes: &[&str]) -> Self {
+        Self {
+            hashes: hashes.iter().map(|h| h.to_string()).collect(),
+            text: String::new(),
+            word: String::new(),
+            word_step: 0,
+            found: Vec::new(),
+        }
+    }
+
+    // Codes found so far, in the order they were written, each only once.
+    pub fn found(&self) -> &[FoundCode] {
+        &self.found
+    }
+
+    // Looks at a char written while running the command at this step.
+    pub fn write(&mut self, c: char, command: Option<usize>, step: u64) {
+        if c.is_ascii_alphanumeric() {
+            if self.word.is_empty() {
+                self.word_step = step;
+            }
+            self.word.push(c);
+            return;
+        }
+        if !self.word.is_empty() {
+            self.end_word(command);
+        }
+        self.push_text(c);
+    }
+
+    fn known(&self, word: &str) -> Option<usize> {
+        let hash = format!("{:x}", md5::compute(word));
+        self.hashes.iter().position(|h| *h == hash)
+    }
+
+    fn end_word(&mut self, command: Option<usize>) {
+        let word = std::mem::take(&mut self.word);
+        let mut known = self.known(&word);
+        let before = self.text.trim_end();
+        let lead_in = LEAD_INS.iter().find(|l| before.ends_with(*l)).copied();
+
+        let mut code = word.clone();
+        let mut mirrored = None;
+        if known.is_none() && lead_in == Some(MIRROR_LEAD_IN) {
+            if let Ok((n, reading)) = mirror::decode(&word, |c| self.known(c)) {
+                known = Some(n);
+                code = reading;
+                mirrored = Some(word.clone());
+            }
+        }
+
+        let is_code = known.is_some() || (lead_in.is_some() && word.len() == CODE_LEN);
+        if is_code && !self.found.iter().any(|f| f.code == code) {
+            self.found.push(FoundCode {
+                code,
+                known,
+                lead_in,
+                mirrored,
+                command,
+                step: self.word_step,
+            });
+        }
+        self.text += &word;
+    }
+
+    fn push_text(&mut self, c: char) {
+        self.text.push(c);
+        if self.text.len() > 2 * TEXT_KEPT {
+            let cut = (self.text.len() - TEXT_KEPT..)
+                .find(|i| self.text.is_char_boundary(*i))
+                .unwrap();
+            self.text.drain(..cut);
+        }
+    }
+}
+
+impl OutputHook for CodeScanner {
+    fn write(&mut self, c: char, command: Option<usize>, step: u64) {
+        CodeScanner::write(self, c, command, step);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::codes::report;
+    use crate::codes::teleporter_code;
+    use crate::maze::patch_code;
+    use crate::vm::program::Program;
+    use crate::vm::Vm;
+
+    fn scan(text: &str, hashes: &[&str]) -> Vec<FoundCode> {
+        let mut scanner = CodeScanner::new(hashes);
+        for (i, c) in text.chars().enumerate() {
+            scanner.write(c, Some(3), i as u64);
+        }
+        scanner.found().to_vec()
+    }
+
+    #[test]
+    fn test_scan() {
+        let text =
+            "Chiseled on the wall of one of the passageways, you see:\n\n    abcdefABCDEF\n\n";
+        let found = scan(text, &[]);
+        assert_eq!(
+            found,
+            [FoundCode {
+                code: "abcdefABCDEF".to_string(),
+                known: None,
+                lead_in: Some(LEAD_INS[3]),
+                mirrored: None,
+                command: Some(3),
+                step: text.find("abc").unwrap() as u64,
+            }]
+        );
+
+        // Known codes are found anywhere, other words need a lead-in.
+        let md5 = format!("{:x}", md5::compute("secret"));
+        let found = scan("A secret, a secret: abcdefABCDEF.\n", &["x", &md5]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            (found[0].code.as_str(), found[0].known),
+            ("secret", Some(1))
+        );
+
+        // A code seen in a mirror is read back when it's known.
+        let md5 = format!("{:x}", md5::compute("bOqd"));
+        let found = scan("Through the mirror, you see \"bpOd\"\n", &[&md5]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].code.as_str(), found[0].known), ("bOqd", Some(0)));
+        assert_eq!(found[0].mirrored.as_deref(), Some("bpOd"));
+    }
+
+    #[test]
+    fn test_scan_commands() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        let scanner = Rc::new(RefCell::new(CodeScanner::default()));
+        vm.terminal_mut().set_output_hook(Box::new(scanner.clone()));
+        for command in report::commands() {
+            if command == "look strange book" {
+                patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
+            }
+            vm.run_actions(&[command]);
+        }
+
+        let scanner = scanner.borrow();
+        let found = scanner.found();
+        let known: Vec<_> = found.iter().map(|f| f.known).collect();
+        // The first code is in the spec, and the last one is seen in a mirror.
+        assert_eq!(
+            known,
+            [
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(5),
+                Some(6),
+                Some(7)
+            ]
+        );
+        let commands: Vec<_> = found.iter().map(|f| f.command).collect();
+        assert_eq!(
+            commands,
+            [None, None, Some(1), Some(16), Some(50), Some(54), Some(83)]
+        );
+        assert!(found.windows(2).all(|w| w[0].step < w[1].step));
+        assert_eq!(found[6].lead_in, Some(LEAD_INS[6]));
+        assert_eq!(found[6].known, Some(7));
+        assert!(found[6].mirrored.is_some());
+    }
+}
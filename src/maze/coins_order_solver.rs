@@ -1,12 +1,10 @@
-#![cfg(test)]
-
 // At some point in the maze, there are 5 coins to place in a specific order.
 // This piece of code finds the right solution that validates:
 // _ + _ * _^2 + _^3 - _ = 399
 
 use itertools::Itertools;
 
-fn find_right_order() -> Vec<&'static str> {
+pub fn find_right_order() -> Vec<&'static str> {
     // There are 5 coins, each with a specific value.
     // You get the value by looking at it: "look red coin".
     const COINS: [(&str, usize); 5] = [
@@ -0,0 +1,308 @@
+//! Maps the world by driving the game: Every exit of every room reachable from where the game
+//! is, coming back to each room with a snapshot of the VM to try its next exit.
+//!
+//! Rooms are told apart by the state of the game rather than by their text, so that rooms that
+//! look alike, like the twisty passages, are still different rooms: The game keeps a pointer
+//! to the current room in memory.
+//!
+//! That pointer isn't hard-coded, as it depends on the binary. Before exploring, each exit of
+//! the first room is taken, and the pointer is the word that changes with every move to a room
+//! with another text, to a different value for each text, leaving out the words holding the
+//! typed command. This needs an exit of the first room leading to a room with another text.
+//! When several words qualify, like in the challenge binary, which keeps the current room at
+//! both 2754 and 2755, the lowest one is used.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::vm::error::VmError;
+use crate::vm::machine::{ExitReason, Vm};
+use crate::vm::snapshot::Snapshot;
+
+use super::parser::{parse_room, RoomView};
+
+// Longer than the buffer the game reads commands to, so that probing commands fill it.
+const PROBE_COMMAND_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum ExploreError {
+    // What the game printed instead of a room.
+    NotInRoom(String),
+    // No word of memory behaves like a pointer to the current room.
+    NoRoomPointer,
+    Fault(VmError),
+}
+
+impl fmt::Display for ExploreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExploreError::NotInRoom(output) => write!(f, "Not in a room: {:?}", output),
+            ExploreError::NoRoomPointer => write!(f, "Cannot find the current room in memory"),
+            ExploreError::Fault(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExploreError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Room {
+    // Address of the room in the game.
+    pub id: u16,
+    pub name: String,
+    pub description: String,
+    // Things of interest when the room was first visited.
+    pub items: Vec<String>,
+    pub exits: Vec<String>,
+}
+
+// Where taking an exit leads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Destination {
+    Room(u16),
+    // The game refuses, with this message, and stays in the room.
+    Blocked(String),
+    // The game ends.
+    End,
+}
+
+// Rooms and where their exits lead.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldMap {
+    pub start: u16,
+    pub rooms: BTreeMap<u16, Room>,
+    pub exits: BTreeMap<(u16, String), Destination>,
+}
+
+impl WorldMap {
+    // Rooms with this name, in id order.
+    pub fn rooms_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Room> + 'a {
+        self.rooms.values().filter(move |r| r.name == name)
+    }
+}
+
+impl fmt::Display for WorldMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for room in self.rooms.values() {
+            write!(f, "{}\t{}", room.id, room.name)?;
+            if !room.items.is_empty() {
+                write!(f, " ({})", room.items.join(", "))?;
+            }
+            writeln!(f)?;
+            for exit in &room.exits {
+                match self.exits.get(&(room.id, exit.clone())) {
+                    Some(Destination::Room(to)) => writeln!(f, "\t{} -> {}", exit, to)?,
+                    Some(Destination::Blocked(msg)) => writeln!(f, "\t{} blocked: {}", exit, msg)?,
+                    Some(Destination::End) | None => writeln!(f, "\t{} -> end", exit)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// What running a command did.
+enum Outcome {
+    Entered(u16, RoomView),
+    Stayed(String),
+    Ended,
+}
+
+// Words the game stores the typed command in, with its length: Those that differ after
+// commands the game doesn't understand, of different lengths and letters. The VM is left in
+// the state it was, output discarded.
+pub fn input_buffer(vm: &mut Vm) -> Result<Option<RangeInclusive<usize>>, VmError> {
+    let start = vm.snapshot();
+    let mut memories = Vec::new();
+    for command in [
+        "x".repeat(PROBE_COMMAND_LEN),
+        "y".repeat(PROBE_COMMAND_LEN),
+        "y".into(),
+    ] {
+        vm.restore(&start);
+        if let ExitReason::Fault(e) = vm.run_actions(&[&command]) {
+            return Err(e);
+        }
+        memories.push(vm.storage().mem.words().to_vec());
+    }
+    vm.restore(&start);
+    vm.take_output();
+
+    let changed: Vec<usize> = (0..memories[0].len())
+        .filter(|a| memories[1..].iter().any(|m| m[*a] != memories[0][*a]))
+        .collect();
+    Ok(changed.first().zip(changed.last()).map(|(a, b)| *a..=*b))
+}
+
+fn same_text(a: &RoomView, b: &RoomView) -> bool {
+    a.name == b.name && a.description == b.description
+}
+
+// Address of the pointer to the current room, found by taking each exit of the room the game
+// is in, as described in the module doc. The VM is left in the state it was.
+fn find_room_pointer(vm: &mut Vm, view: &RoomView) -> Result<u16, ExploreError> {
+    let buffer = input_buffer(vm).map_err(ExploreError::Fault)?;
+    let start = vm.snapshot();
+    // Candidate addresses, with the room text and value they had after each move.
+    let mut candidates: Option<BTreeMap<usize, Vec<(RoomView, u16)>>> = None;
+    for exit in &view.exits {
+        vm.restore(&start);
+        let reason = vm.run_actions(&[&format!("go {}", exit)]);
+        let output = vm.take_output();
+        if let ExitReason::Fault(e) = reason {
+            return Err(ExploreError::Fault(e));
+        }
+        let Some(to) = parse_room(&output).filter(|to| !same_text(to, view)) else {
+            continue;
+        };
+        let words = vm.storage().mem.words();
+        let changed: BTreeSet<usize> = (0..words.len())
+            .filter(|a| words[*a] != start.mem[*a])
+            .filter(|a| !buffer.as_ref().is_some_and(|b| b.contains(a)))
+            .collect();
+        let candidates =
+            candidates.get_or_insert_with(|| changed.iter().map(|a| (*a, Vec::new())).collect());
+        candidates.retain(|a, values| {
+            let value = words[*a];
+            // Rooms with other texts are at other addresses. This rules out things like a
+            // move counter.
+            let kept =
+                changed.contains(a) && values.iter().all(|(r, v)| same_text(r, &to) || *v != value);
+            values.push((to.clone(), value));
+            kept
+        });
+    }
+    vm.restore(&start);
+    candidates
+        .and_then(|c| c.into_keys().next())
+        .map(|a| a as u16)
+        .ok_or(ExploreError::NoRoomPointer)
+}
+
+// Runs a command, from the room the game is in.
+fn run_command(vm: &mut Vm, room_pointer: u16, command: &str) -> Result<Outcome, ExploreError> {
+    let before = vm.storage().mem.read(room_pointer);
+    let reason = vm.run_actions(&[command]);
+    let output = vm.take_output();
+    match reason {
+        ExitReason::InputExhausted => {}
+        ExitReason::Halted => return Ok(Outcome::Ended),
+        ExitReason::Fault(e) => return Err(ExploreError::Fault(e)),
+    }
+    let room = vm.storage().mem.read(room_pointer);
+    match parse_room(&output) {
+        Some(view) => Ok(Outcome::Entered(room, view)),
+        None if room == before => {
+            let message = output.trim().trim_end_matches("What do you do?").trim();
+            Ok(Outcome::Stayed(message.to_string()))
+        }
+        None => Err(ExploreError::NotInRoom(output)),
+    }
+}
+
+fn room(id: u16, view: RoomView) -> Room {
+    Room {
+        id,
+        name: view.name,
+        description: view.description,
+        items: view.items,
+        exits: view.exits,
+    }
+}
+
+// Maps all the rooms reachable from the room the game is in, waiting for a command.
+// Only exits are taken: The inventory stays the same, so rooms that need items aren't reached
+// without them. The VM is left in an unspecified state.
+pub fn explore(vm: &mut Vm) -> Result<WorldMap, ExploreError> {
+    vm.take_output();
+    if let ExitReason::Fault(e) = vm.run_actions(&["look"]) {
+        return Err(ExploreError::Fault(e));
+    }
+    let look = vm.take_output();
+    let view = parse_room(&look).ok_or(ExploreError::NotInRoom(look))?;
+    let room_pointer = find_room_pointer(vm, &view)?;
+    let start = vm.storage().mem.read(room_pointer);
+
+    let mut map = WorldMap {
+        start,
+        ..WorldMap::default()
+    };
+    map.rooms.insert(start, room(start, view));
+    let mut to_visit: VecDeque<(u16, Snapshot)> = VecDeque::from([(start, vm.snapshot())]);
+
+    while let Some((id, snapshot)) = to_visit.pop_front() {
+        for exit in map.rooms[&id].exits.clone() {
+            vm.restore(&snapshot);
+            let to = match run_command(vm, room_pointer, &format!("go {}", exit))? {
+                Outcome::Entered(to, view) => {
+                    if let Entry::Vacant(e) = map.rooms.entry(to) {
+                        e.insert(room(to, view));
+                        to_visit.push_back((to, vm.snapshot()));
+                    }
+                    Destination::Room(to)
+                }
+                Outcome::Stayed(message) => Destination::Blocked(message),
+                Outcome::Ended => Destination::End,
+            };
+            map.exits.insert((id, exit), to);
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codes::teleporter_code;
+    use crate::maze::maze_commands::COMMANDS;
+    use crate::maze::patch_code;
+    use crate::vm::program::Program;
+
+    #[test]
+    fn test_explore() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        let map = explore(&mut vm).unwrap();
+
+        let foothills = &map.rooms[&map.start];
+        assert_eq!(foothills.name, "Foothills");
+        assert_eq!(foothills.items, ["tablet"]);
+        let Destination::Room(doorway) = map.exits[&(map.start, "doorway".to_string())] else {
+            panic!("Doorway doesn't lead to a room");
+        };
+        assert_eq!(map.rooms[&doorway].name, "Dark cave");
+
+        // The twisty passages all have the same name, but are different rooms.
+        assert_eq!(map.rooms_named("Twisty passages").count(), 8);
+        let can = map.rooms.values().find(|r| r.items == ["can"]).unwrap();
+        assert_eq!(can.name, "Twisty passages");
+    }
+
+    #[test]
+    fn test_room_pointer() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        vm.run_actions(&["look"]);
+        let view = parse_room(&vm.take_output()).unwrap();
+        assert_eq!(input_buffer(&mut vm), Ok(Some(25988..=26020)));
+        assert_eq!(find_room_pointer(&mut vm, &view), Ok(2754));
+    }
+
+    #[test]
+    fn test_explore_island() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        vm.run_actions(&COMMANDS[0..=51]);
+        patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
+        vm.run_actions(&COMMANDS[52..=54]);
+        let map = explore(&mut vm).unwrap();
+
+        assert_eq!(map.rooms_named("Vault Lock").count(), 14);
+        let antechamber = map.rooms_named("Vault Antechamber").next().unwrap();
+        assert_eq!(antechamber.items, ["orb"]);
+        let door = map.rooms_named("Vault Door").next().unwrap();
+        assert_eq!(
+            map.exits[&(door.id, "vault".to_string())],
+            Destination::Blocked("The vault door is sealed.".to_string())
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Exports a map built by the explorer, so it can be regenerated from the binary and diffed.
+//!
+//! DOT, for Graphviz: One node per room, labelled with its name and items, and one edge per
+//! exit, labelled with the exit. Exits that end the game go to a single "end" node, and blocked
+//! exits loop back to their room with a dashed edge.
+//!
+//! JSON, as one object:
+//!
+//! ```text
+//! {
+//!   "start": 2339,                  // id of the room the exploration started from
+//!   "rooms": [{
+//!     "id": 2339,                   // address of the room in the game
+//!     "name": "Foothills",
+//!     "description_md5": "…",       // MD5 of the description, as lowercase hex
+//!     "items": ["tablet"]           // things of interest when first visited
+//!   }],
+//!   "exits": [{
+//!     "from": 2339,
+//!     "exit": "doorway",            // what follows "go" in the command
+//!     "to": 2349,                   // null if the exit doesn't lead to a room
+//!     "blocked": null               // message of the game if it refuses to take the exit
+//!   }]                              // (both null if the game ends)
+//! }
+//! ```
+//!
+//! Rooms are sorted by id, exits by room then exit, so the output is stable.
+
+use std::fmt::Write;
+
+use super::explorer::{Destination, WorldMap};
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_strings(strings: &[String]) -> String {
+    let strings: Vec<_> = strings
+        .iter()
+        .map(|s| format!("\"{}\"", escape(s)))
+        .collect();
+    format!("[{}]", strings.join(","))
+}
+
+pub fn to_json(map: &WorldMap) -> String {
+    let rooms: Vec<_> = map
+        .rooms
+        .values()
+        .map(|r| {
+            format!(
+                r#"    {{"id":{},"name":"{}","description_md5":"{:x}","items":{}}}"#,
+                r.id,
+                escape(&r.name),
+                md5::compute(r.description.as_bytes()),
+                json_strings(&r.items)
+            )
+        })
+        .collect();
+    let exits: Vec<_> = map
+        .exits
+        .iter()
+        .map(|((from, exit), to)| {
+            let (to, blocked) = match to {
+                Destination::Room(to) => (to.to_string(), "null".to_string()),
+                Destination::Blocked(msg) => ("null".to_string(), format!("\"{}\"", escape(msg))),
+                Destination::End => ("null".to_string(), "null".to_string()),
+            };
+            format!(
+                r#"    {{"from":{},"exit":"{}","to":{},"blocked":{}}}"#,
+                from,
+                escape(exit),
+                to,
+                blocked
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"start\":{},\n  \"rooms\":[\n{}\n  ],\n  \"exits\":[\n{}\n  ]\n}}\n",
+        map.start,
+        rooms.join(",\n"),
+        exits.join(",\n")
+    )
+}
+
+pub fn to_dot(map: &WorldMap) -> String {
+    let mut dot = String::from("digraph world {\n");
+    for room in map.rooms.values() {
+        let mut label = escape(&room.name);
+        for item in &room.items {
+            write!(label, "\\n[{}]", escape(item)).unwrap();
+        }
+        let shape = if room.id == map.start {
+            ",shape=doublecircle"
+        } else {
+            ""
+        };
+        writeln!(dot, "  r{} [label=\"{}\"{}];", room.id, label, shape).unwrap();
+    }
+    if map.exits.values().any(|to| *to == Destination::End) {
+        dot += "  end [label=\"end\",shape=box];\n";
+    }
+    for ((from, exit), to) in &map.exits {
+        let (to, style) = match to {
+            Destination::Room(to) => (format!("r{}", to), ""),
+            Destination::Blocked(_) => (format!("r{}", from), ",style=dashed"),
+            Destination::End => ("end".to_string(), ""),
+        };
+        writeln!(
+            dot,
+            "  r{} -> {} [label=\"{}\"{}];",
+            from,
+            to,
+            escape(exit),
+            style
+        )
+        .unwrap();
+    }
+    dot += "}\n";
+    dot
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maze::explorer::Room;
+
+    fn small_map() -> WorldMap {
+        let mut map = WorldMap {
+            start: 1,
+            ..WorldMap::default()
+        };
+        map.rooms.insert(
+            1,
+            Room {
+                id: 1,
+                name: "Hall".to_string(),
+                description: "A \"big\" hall.".to_string(),
+                items: vec!["lamp".to_string()],
+                exits: vec!["north".to_string(), "down".to_string(), "up".to_string()],
+            },
+        );
+        map.rooms.insert(
+            2,
+            Room {
+                id: 2,
+                name: "Attic".to_string(),
+                description: String::new(),
+                items: Vec::new(),
+                exits: vec!["south".to_string()],
+            },
+        );
+        map.exits
+            .insert((1, "north".to_string()), Destination::Room(2));
+        map.exits.insert((1, "down".to_string()), Destination::End);
+        map.exits.insert(
+            (1, "up".to_string()),
+            Destination::Blocked("It's locked.".to_string()),
+        );
+        map.exits
+            .insert((2, "south".to_string()), Destination::Room(1));
+        map
+    }
+
+    #[test]
+    fn test_json() {
+        let json = to_json(&small_map());
+        let expected = format!(
+            r#"{{
+  "start":1,
+  "rooms":[
+    {{"id":1,"name":"Hall","description_md5":"{:x}","items":["lamp"]}},
+    {{"id":2,"name":"Attic","description_md5":"d41d8cd98f00b204e9800998ecf8427e","items":[]}}
+  ],
+  "exits":[
+    {{"from":1,"exit":"down","to":null,"blocked":null}},
+    {{"from":1,"exit":"north","to":2,"blocked":null}},
+    {{"from":1,"exit":"up","to":null,"blocked":"It's locked."}},
+    {{"from":2,"exit":"south","to":1,"blocked":null}}
+  ]
+}}
+"#,
+            md5::compute("A \"big\" hall.")
+        );
+        assert_eq!(json, expected);
+        assert_eq!(escape("a\"b\\c\n\t"), "a\\\"b\\\\c\\n\\u0009");
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = to_dot(&small_map());
+        assert_eq!(
+            dot,
+            r#"digraph world {
+  r1 [label="Hall\n[lamp]",shape=doublecircle];
+  r2 [label="Attic"];
+  end [label="end",shape=box];
+  r1 -> end [label="down"];
+  r1 -> r2 [label="north"];
+  r1 -> r1 [label="up",style=dashed];
+  r2 -> r1 [label="south"];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Reading text seen in a mirror: It's reversed, and each glyph is reflected left to right.
+//!
+//! Some glyphs read as another one once reflected, like b and d or ( and ), and some can be read
+//! as several chars, like O and 0. So a text can have several readings: The right one is picked with the
+//! MD5 of the codes.
+
+use std::fmt;
+
+// Glyphs as seen in a mirror, with the chars that look like them once reflected.
+// Glyphs not listed don't look like any char once reflected.
+const REFLECTIONS: [(char, &str); 51] = [
+    ('A', "A"),
+    ('H', "H"),
+    ('I', "Il"),
+    ('M', "M"),
+    ('O', "O0"),
+    ('T', "T"),
+    ('U', "U"),
+    ('V', "V"),
+    ('W', "W"),
+    ('X', "X"),
+    ('Y', "Y"),
+    ('b', "d"),
+    ('d', "b"),
+    ('i', "i"),
+    ('l', "Il"),
+    ('m', "m"),
+    ('n', "n"),
+    ('o', "o"),
+    ('p', "q"),
+    ('q', "p"),
+    ('u', "u"),
+    ('v', "v"),
+    ('w', "w"),
+    ('x', "x"),
+    ('0', "O0"),
+    ('8', "8"),
+    ('(', ")"),
+    (')', "("),
+    ('[', "]"),
+    (']', "["),
+    ('{', "}"),
+    ('}', "{"),
+    ('<', ">"),
+    ('>', "<"),
+    ('/', "\\"),
+    ('\\', "/"),
+    (' ', " "),
+    ('!', "!"),
+    ('"', "\""),
+    ('\'', "'"),
+    ('*', "*"),
+    ('+', "+"),
+    ('-', "-"),
+    ('.', "."),
+    (':', ":"),
+    ('=', "="),
+    ('^', "^"),
+    ('_', "_"),
+    ('|', "|"),
+    ('#', "#"),
+    ('%', "%"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum MirrorError {
+    // This glyph, at this position in the text seen, doesn't look like any char once reflected.
+    NoReflection(usize, char),
+    // None of the readings is a known code: All of them are given.
+    Unknown(Vec<String>),
+}
+
+impl fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MirrorError::NoReflection(i, c) => {
+                write!(f, "Glyph {:?} at {} has no reflection", c, i)
+            }
+            MirrorError::Unknown(candidates) => {
+                write!(f, "No known code among {}", candidates.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for MirrorError {}
+
+// Chars that look like the glyph once reflected, empty if none does.
+pub fn reflections(glyph: char) -> &'static str {
+    REFLECTIONS
+        .iter()
+        .find(|(g, _)| *g == glyph)
+        .map_or("", |(_, chars)| chars)
+}
+
+// All the texts that look like what was seen in the mirror.
+pub fn candidates(seen: &str) -> Result<Vec<String>, MirrorError> {
+    let mut candidates = vec![String::new()];
+    for (i, glyph) in seen.chars().rev().enumerate() {
+        let chars = reflections(glyph);
+        if chars.is_empty() {
+            return Err(MirrorError::NoReflection(
+                seen.chars().count() - 1 - i,
+                glyph,
+            ));
+        }
+        candidates = candidates
+            .iter()
+            .flat_map(|c| chars.chars().map(move |r| format!("{}{}", c, r)))
+            .collect();
+    }
+    Ok(candidates)
+}
+
+// The code, and its number, that was seen in the mirror.
+pub fn decode(
+    seen: &str,
+    code_number: impl Fn(&str) -> Option<usize>,
+) -> Result<(usize, String), MirrorError> {
+    let candidates = candidates(seen)?;
+    candidates
+        .iter()
+        .find_map(|c| code_number(c).map(|n| (n, c.clone())))
+        .ok_or(MirrorError::Unknown(candidates))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codes::codes_check::code_number;
+    use crate::codes::{report, teleporter_code};
+    use crate::maze::parser::{codes, parse};
+    use crate::maze::patch_code;
+    use crate::vm::program::Program;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(candidates("bqd"), Ok(vec!["bpd".to_string()]));
+        assert_eq!(
+            candidates("lOx"),
+            Ok(vec![
+                "xOI".to_string(),
+                "xOl".to_string(),
+                "x0I".to_string(),
+                "x0l".to_string()
+            ])
+        );
+        assert_eq!(candidates("bad"), Err(MirrorError::NoReflection(1, 'a')));
+        assert_eq!(candidates("(a/b]"), Err(MirrorError::NoReflection(1, 'a')));
+        assert_eq!(candidates("<(d/b]"), Ok(vec!["[d\\b)>".to_string()]));
+        assert_eq!(candidates(""), Ok(vec![String::new()]));
+    }
+
+    #[test]
+    fn test_decode() {
+        let known = |c: &str| (c == "0xqd").then_some(4);
+        assert_eq!(decode("bpxO", known), Ok((4, "0xqd".to_string())));
+        assert_eq!(
+            decode("Ob", code_number),
+            Err(MirrorError::Unknown(vec![
+                "dO".to_string(),
+                "d0".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_decode_game() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        let mut output = String::new();
+        for command in report::commands() {
+            if command == "look strange book" {
+                patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
+            }
+            vm.run_actions(&[command]);
+            output = vm.take_output();
+        }
+        // The last command uses the mirror.
+        let events = parse(Some("use mirror"), &output);
+        let seen = codes(&events).next().unwrap();
+        assert_eq!(decode(seen, code_number).unwrap().0, 7);
+    }
+}
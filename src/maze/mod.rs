@@ -1,7 +1,13 @@
-mod coins_order_solver;
-mod orb;
+pub mod code_scanner;
+pub mod coins_order_solver;
+pub mod explorer;
+pub mod map_export;
+pub mod mirror;
+pub mod orb;
+pub mod parser;
 
 pub mod patch_code;
+pub mod planner;
 pub mod teleporter_code;
 
 pub mod maze_commands;
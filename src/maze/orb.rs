@@ -1,21 +1,31 @@
-//! Code to solve the following problem:
-//! Find the shortest path from bottom-left to top-right that results in a sum of 30.
+//! Code to solve the vault puzzle: Find the shortest path through a grid of values and operators,
+//! from a start room to the vault, that gives the orb the right weight.
 //!
+//! In the game, the path goes from bottom-left to top-right and must result in a weight of 30:
+//!
+//! ```text
 //! *    8   -   1
 //! 4    *   11  *
 //! +    4   -   18
 //! 22   -   9   *
+//! ```
+//!
+//! The orb starts with the value of the start room. Stepping from an operator onto a value
+//! applies the operator to the weight. A state is the position and the weight, so a BFS over
+//! them gives the shortest path.
 
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum Room {
+pub enum Room {
     Value(i32),
     Plus,
     Minus,
     Mult,
 }
-use itertools::Itertools;
 use Room::*;
 
 impl fmt::Display for Room {
@@ -29,155 +39,222 @@ impl fmt::Display for Room {
     }
 }
 
-// Position in the graph, room, list of connected positions.
-// We need the position in the graph as some rooms have the same content.
-type Graph = Vec<(usize, Room, Vec<usize>)>;
-
-#[allow(dead_code)]
-pub fn solve_orb() -> Vec<usize> {
-    // From left-right, top-up: Nodes and their connections.
-    // 12   13  14  15
-    // 8    9   10  11
-    // 4    5   6   7
-    // 0    1   2   3
-    #[rustfmt::skip]
-    let graph: Graph = vec![
-        (0,  Value(22), vec![1, 4]),
-        (1,  Minus,     vec![0, 5, 2]),
-        (2,  Value(9),  vec![1, 6, 3]),
-        (3,  Mult,      vec![2, 7]),
-        (4,  Plus,      vec![0, 5, 8]),
-        (5,  Value(4),  vec![1, 6, 9, 4]),
-        (6,  Minus,     vec![2, 5, 10, 7]),
-        (7,  Value(18), vec![3, 6, 11]),
-        (8,  Value(4),  vec![4, 9, 12]),
-        (9,  Mult,      vec![8, 5, 10, 13]),
-        (10, Value(11), vec![6, 9, 14, 11]),
-        (11, Mult,      vec![7, 10, 15]),
-        (12, Mult,      vec![8, 13]),
-        (13, Value(8),  vec![12, 9, 14]),
-        (14, Minus,     vec![13, 10, 15]),
-        (15, Value(1),  vec![14, 11]),
-    ];
-
-    let mut shortest_found_path: Vec<usize> = Vec::new();
-    traverse_graph(&graph, 0, &[0], &mut shortest_found_path);
-    println!(
-        "Length={}, path: {}",
-        shortest_found_path.len(),
-        positions_to_str(&graph, &shortest_found_path)
-    );
-
-    shortest_found_path
-}
+// Position in the grid, as (row, column).
+pub type Pos = (usize, usize);
 
-#[test]
-fn test_solve_orb() {
-    let path = solve_orb();
-    assert_eq!(path.len(), 13);
-}
+// Position and weight of the orb.
+type State = (Pos, i32);
 
-// Calculates the weight of the list of rooms.
-fn calc_weight(rooms: &[Room]) -> i32 {
-    assert!(!rooms.is_empty());
-    let initial_val = if let Value(v) = rooms[0] {
-        v
-    } else {
-        panic!("Path doesn't start with a value");
-    };
-    if rooms.len() == 1 {
-        return initial_val;
-    }
+// Directions, with their move in rows and columns.
+const DIRECTIONS: [(&str, isize, isize); 4] = [
+    ("north", -1, 0),
+    ("east", 0, 1),
+    ("south", 1, 0),
+    ("west", 0, -1),
+];
 
-    rooms[1..].chunks(2).fold(initial_val, |acc, r| {
-        if r.len() < 2 {
-            return acc;
-        }
-        if let Value(val) = r[1] {
-            match r[0] {
-                Plus => acc + val,
-                Minus => acc - val,
-                Mult => acc * val,
-                Value(_) => panic!("Wrong path, got value instead of sign"),
-            }
-        } else {
-            panic!("Wrong path, got sign instead of value");
-        }
-    })
+#[derive(Debug, PartialEq)]
+pub enum VaultError {
+    // A position outside of the grid.
+    OutOfGrid(Pos),
+    // All the states were searched without reaching the goal with the weight.
+    NoPath,
 }
 
-#[test]
-fn test_calc() {
-    assert_eq!(calc_weight(&[Value(22)]), 22);
-    assert_eq!(calc_weight(&[Value(22), Plus]), 22);
-    assert_eq!(calc_weight(&[Value(22), Plus, Value(13)]), 35);
-    assert_eq!(calc_weight(&[Value(22), Plus, Value(13), Minus]), 35);
-    assert_eq!(
-        calc_weight(&[Value(22), Plus, Value(13), Minus, Value(30)]),
-        5
-    );
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VaultError::OutOfGrid(pos) => write!(f, "Position {:?} is outside of the grid", pos),
+            VaultError::NoPath => write!(f, "No path gives the target weight"),
+        }
+    }
 }
 
-// Converts the list of positions forming a path to some nicer string.
-fn positions_to_str(graph: &Graph, positions: &[usize]) -> String {
-    positions
-        .iter()
-        .map(|p| {
-            let e = &graph[*p];
-            format!("{} ({})", e.0, e.1)
-        })
-        .join(" ")
-}
+impl std::error::Error for VaultError {}
 
-fn positions_to_room_list(graph: &Graph, positions: &[usize]) -> Vec<Room> {
-    positions.iter().map(|p| graph[*p].1).collect()
+#[derive(Debug, Clone)]
+pub struct Vault {
+    // Rows from north to south, each from west to east.
+    pub grid: Vec<Vec<Room>>,
+    pub start: Pos,
+    pub goal: Pos,
+    pub target: i32,
+    // Weights the orb can have: Going out of them, it vanishes.
+    pub weights: RangeInclusive<i32>,
+    // Going back to the start is forbidden, as it resets the orb.
+    pub no_return_to_start: bool,
+    // The goal cannot be left once reached, whatever the weight.
+    pub goal_is_terminal: bool,
 }
 
-// The function that finds all valid path.
-// Graph traversal, with DFS, recursive.
-fn traverse_graph(graph: &Graph, pos: usize, path: &[usize], shortest_found_path: &mut Vec<usize>) {
-    const START_POS: usize = 0;
-    const END_POS: usize = 15;
-    const END_WEIGHT: i32 = 30;
+impl Vault {
+    // The vault of the game.
+    #[rustfmt::skip]
+    pub fn orb() -> Self {
+        Self {
+            grid: vec![
+                vec![Mult,      Value(8), Minus,     Value(1)],
+                vec![Value(4),  Mult,     Value(11), Mult],
+                vec![Plus,      Value(4), Minus,     Value(18)],
+                vec![Value(22), Minus,    Value(9),  Mult],
+            ],
+            start: (3, 0),
+            goal: (0, 3),
+            target: 30,
+            // Weight can't be negative, and the VM numbers are 15 bits.
+            weights: 0..=32767,
+            no_return_to_start: true,
+            goal_is_terminal: true,
+        }
+    }
 
-    let rooms = positions_to_room_list(graph, path);
-    let w = calc_weight(&rooms);
+    fn room(&self, pos: Pos) -> Option<Room> {
+        self.grid.get(pos.0).and_then(|row| row.get(pos.1)).copied()
+    }
 
-    // Weight cannot be negative
-    if w < 0 {
-        return;
+    // The weight after stepping from one room to the next, None if the orb vanishes.
+    fn step_weight(&self, weight: i32, from: Room, to: Room) -> Option<i32> {
+        let weight = match (from, to) {
+            (Plus, Value(v)) => weight.checked_add(v)?,
+            (Minus, Value(v)) => weight.checked_sub(v)?,
+            (Mult, Value(v)) => weight.checked_mul(v)?,
+            _ => weight,
+        };
+        Some(weight).filter(|w| self.weights.contains(w))
     }
 
-    if pos == END_POS {
-        if w == END_WEIGHT {
-            // println!("Found {}", positions_to_str(&graph, &path));
-            if shortest_found_path.is_empty() || shortest_found_path.len() > path.len() {
-                *shortest_found_path = path.to_vec();
+    // The shortest list of commands from the start to the goal, reaching it with the target weight.
+    pub fn solve(&self) -> Result<Vec<String>, VaultError> {
+        let start_room = self
+            .room(self.start)
+            .ok_or(VaultError::OutOfGrid(self.start))?;
+        self.room(self.goal)
+            .ok_or(VaultError::OutOfGrid(self.goal))?;
+        let start_weight = match start_room {
+            Value(v) => v,
+            _ => 0,
+        };
+
+        // Each state seen, with the state and direction leading to it.
+        let start = (self.start, start_weight);
+        let mut parents: HashMap<State, Option<(State, &str)>> = HashMap::new();
+        parents.insert(start, None);
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(state) = queue.pop_front() {
+            let (pos, weight) = state;
+            if pos == self.goal && weight == self.target {
+                let mut commands = Vec::new();
+                let mut state = state;
+                while let Some((parent, direction)) = parents[&state] {
+                    commands.push(format!("go {}", direction));
+                    state = parent;
+                }
+                commands.reverse();
+                return Ok(commands);
+            }
+            if pos == self.goal && self.goal_is_terminal {
+                continue;
+            }
+
+            let room = self.room(pos).unwrap();
+            for (direction, dr, dc) in DIRECTIONS {
+                let (Some(row), Some(col)) =
+                    (pos.0.checked_add_signed(dr), pos.1.checked_add_signed(dc))
+                else {
+                    continue;
+                };
+                let next_pos = (row, col);
+                if self.no_return_to_start && next_pos == self.start {
+                    continue;
+                }
+                let Some(next_room) = self.room(next_pos) else {
+                    continue;
+                };
+                let Some(next_weight) = self.step_weight(weight, room, next_room) else {
+                    continue;
+                };
+                let next = (next_pos, next_weight);
+                if let Entry::Vacant(e) = parents.entry(next) {
+                    e.insert(Some((state, direction)));
+                    queue.push_back(next);
+                }
             }
         }
-        // Once in the Vault room, we cannot leave anymore
-        return;
+        Err(VaultError::NoPath)
     }
+}
 
-    // If we are on a path bigger than the shortest already found, no need to pursue
-    if !shortest_found_path.is_empty() && path.len() > shortest_found_path.len() {
-        return;
-    }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codes::teleporter_code;
+    use crate::maze::maze_commands::COMMANDS;
+    use crate::maze::parser;
+    use crate::maze::patch_code;
+    use crate::vm::program::Program;
+    use crate::vm::Vm;
 
-    // Avoid going too long
-    if path.len() > 16 {
-        return;
-    }
+    #[test]
+    fn test_solve_constraints() {
+        let vault = Vault {
+            grid: vec![vec![Value(2), Plus, Value(3)]],
+            start: (0, 0),
+            goal: (0, 2),
+            target: 5,
+            weights: 0..=100,
+            no_return_to_start: true,
+            goal_is_terminal: true,
+        };
+        assert_eq!(vault.solve().unwrap(), ["go east", "go east"]);
 
-    for neighbor_pos in &graph[pos].2 {
-        // We cannot go back to start room anymore, as this causes orb to vanish.
-        if *neighbor_pos == START_POS {
-            continue;
-        }
+        // 2 + 2 + 3 needs going back to the start.
+        let vault = Vault { target: 7, ..vault };
+        assert_eq!(vault.solve(), Err(VaultError::NoPath));
+        let free = Vault {
+            no_return_to_start: false,
+            ..vault.clone()
+        };
+        assert_eq!(
+            free.solve().unwrap(),
+            ["go east", "go west", "go east", "go east"]
+        );
+
+        // 2 + 3 + 3 needs leaving the goal.
+        let vault = Vault { target: 8, ..vault };
+        assert_eq!(vault.solve(), Err(VaultError::NoPath));
+        let free = Vault {
+            goal_is_terminal: false,
+            ..vault.clone()
+        };
+        assert_eq!(
+            free.solve().unwrap(),
+            ["go east", "go east", "go west", "go east"]
+        );
+
+        let vault = Vault {
+            goal: (1, 0),
+            ..vault
+        };
+        assert_eq!(vault.solve(), Err(VaultError::OutOfGrid((1, 0))));
+    }
 
-        let mut new_path = path.to_vec();
-        new_path.push(*neighbor_pos);
+    #[test]
+    fn test_solve_orb() {
+        let commands = Vault::orb().solve().unwrap();
+        let take_orb = COMMANDS.iter().position(|c| *c == "take orb").unwrap();
+        assert_eq!(commands, COMMANDS[take_orb + 1..=take_orb + 12]);
 
-        traverse_graph(graph, *neighbor_pos, &new_path, shortest_found_path);
+        // The vault door opens with the orb carried along the path.
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        vm.run_actions(&COMMANDS[0..=51]);
+        patch_code::patch_with_code(vm.storage_mut(), teleporter_code());
+        vm.run_actions(&COMMANDS[52..=take_orb]);
+        let commands: Vec<_> = commands.iter().map(String::as_str).collect();
+        vm.run_actions(&commands);
+        vm.take_output();
+        vm.run_actions(&["go vault"]);
+        let room = parser::parse_room(&vm.take_output()).unwrap();
+        assert_eq!(room.name, "Vault");
     }
 }
@@ -0,0 +1,295 @@
+//! Splits what the game prints into typed events, so tools don't have to search the text.
+//!
+//! The game prints paragraphs separated by blank lines: A room is a header with its
+//! description, then the list of things of interest and of exits, and each reply ends with the
+//! prompt for the next command.
+
+use std::fmt;
+
+const PROMPT: &str = "What do you do?";
+
+// Replies to "use" when nothing happens.
+const USE_REFUSALS: [&str; 2] = [
+    "You can't find that in your pack.",
+    "You aren't sure how to use that.",
+];
+
+// Text around the codes announced within a sentence.
+const CODE_LEAD_INS: [(&str, &str); 4] = [
+    ("into the challenge website: ", ""),
+    ("completion code is: ", ""),
+    ("You find yourself writing \"", "\" on the tablet"),
+    ("Through the mirror, you see \"", "\" scrawled"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // "== Name ==", when entering or looking at a room.
+    Room(String),
+    Description(String),
+    // Things of interest in the room.
+    Items(Vec<String>),
+    Exits(Vec<String>),
+    Inventory(Vec<String>),
+    // Confirmation that the item of the command was taken.
+    Taken(String),
+    // What happened using the item of the command.
+    Used { item: String, message: String },
+    Code(String),
+    // Any other paragraph.
+    Text(String),
+    // The game waits for a command.
+    Prompt,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Room(name) => write!(f, "room: {}", name),
+            Event::Description(text) => write!(f, "description: {}", text),
+            Event::Items(items) => write!(f, "items: {}", items.join(", ")),
+            Event::Exits(exits) => write!(f, "exits: {}", exits.join(", ")),
+            Event::Inventory(items) => write!(f, "inventory: {}", items.join(", ")),
+            Event::Taken(item) => write!(f, "taken: {}", item),
+            Event::Used { item, message } => write!(f, "used {}: {}", item, message),
+            Event::Code(code) => write!(f, "code: {}", code),
+            Event::Text(text) => write!(f, "text: {}", text),
+            Event::Prompt => write!(f, "prompt"),
+        }
+    }
+}
+
+// What the game prints when entering or looking at a room.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomView {
+    pub name: String,
+    pub description: String,
+    pub items: Vec<String>,
+    pub exits: Vec<String>,
+}
+
+// The "- item" lines of a list.
+fn list(lines: &[&str]) -> Vec<String> {
+    lines
+        .iter()
+        .filter_map(|l| l.strip_prefix("- "))
+        .map(String::from)
+        .collect()
+}
+
+fn is_exits_heading(line: &str) -> bool {
+    line == "There is 1 exit:" || (line.starts_with("There are ") && line.ends_with(" exits:"))
+}
+
+// Codes given within the text.
+fn inline_codes(text: &str) -> Vec<String> {
+    let mut codes = Vec::new();
+    for (before, after) in CODE_LEAD_INS {
+        for (i, _) in text.match_indices(before) {
+            let rest = &text[i + before.len()..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if end > 0 && rest[end..].starts_with(after) {
+                codes.push(rest[..end].to_string());
+            }
+        }
+    }
+    codes
+}
+
+fn parse_paragraph(paragraph: &str, events: &mut Vec<Event>) {
+    let lines: Vec<&str> = paragraph.lines().collect();
+    let first = lines[0];
+    if let Some(name) = first
+        .strip_prefix("== ")
+        .and_then(|l| l.strip_suffix(" =="))
+    {
+        events.push(Event::Room(name.to_string()));
+        if lines.len() > 1 {
+            events.push(Event::Description(lines[1..].join("\n")));
+        }
+    } else if first == "Things of interest here:" {
+        events.push(Event::Items(list(&lines[1..])));
+    } else if is_exits_heading(first) {
+        events.push(Event::Exits(list(&lines[1..])));
+    } else if first == "Your inventory:" {
+        events.push(Event::Inventory(list(&lines[1..])));
+    } else if paragraph == PROMPT {
+        events.push(Event::Prompt);
+    } else if lines.len() == 1
+        && first.starts_with(' ')
+        && first.trim().chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        // Codes shown on their own, after the text introducing them.
+        events.push(Event::Code(first.trim().to_string()));
+    } else {
+        events.push(Event::Text(paragraph.to_string()));
+        events.extend(inline_codes(paragraph).into_iter().map(Event::Code));
+    }
+}
+
+// Events in the output of the game, replying to the command if any.
+pub fn parse(command: Option<&str>, output: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    for paragraph in output.split("\n\n") {
+        let paragraph = paragraph.trim_matches('\n');
+        if paragraph.trim().is_empty() {
+            continue;
+        }
+        parse_paragraph(paragraph, &mut events);
+        // Rooms can have more paragraphs of description, like warnings.
+        if let [.., Event::Description(_), last] = events.as_mut_slice() {
+            if let Event::Text(text) = last {
+                *last = Event::Description(std::mem::take(text));
+            }
+        }
+    }
+
+    // The reply to the command is the first paragraph.
+    let command = command.map(str::trim);
+    if let Some(Event::Text(text)) = events.first() {
+        if let Some(item) = command.and_then(|c| c.strip_prefix("take ")) {
+            if text == "Taken." {
+                events[0] = Event::Taken(item.to_string());
+            }
+        } else if let Some(item) = command.and_then(|c| c.strip_prefix("use ")) {
+            if !USE_REFUSALS.contains(&text.as_str()) {
+                events[0] = Event::Used {
+                    item: item.to_string(),
+                    message: text.clone(),
+                };
+            }
+        }
+    }
+    events
+}
+
+// The last room in the events.
+pub fn room(events: &[Event]) -> Option<RoomView> {
+    let start = events.iter().rposition(|e| matches!(e, Event::Room(_)))?;
+    let Event::Room(name) = &events[start] else {
+        return None;
+    };
+    let mut view = RoomView {
+        name: name.clone(),
+        description: String::new(),
+        items: Vec::new(),
+        exits: Vec::new(),
+    };
+    for event in &events[start + 1..] {
+        match event {
+            Event::Description(text) if view.description.is_empty() => {
+                view.description = text.clone()
+            }
+            Event::Description(text) => view.description += &format!("\n\n{}", text),
+            Event::Items(items) => view.items = items.clone(),
+            Event::Exits(exits) => view.exits = exits.clone(),
+            _ => break,
+        }
+    }
+    Some(view)
+}
+
+// The last room printed in the output.
+pub fn parse_room(output: &str) -> Option<RoomView> {
+    room(&parse(None, output))
+}
+
+// The last inventory in the events.
+pub fn inventory(events: &[Event]) -> Option<&[String]> {
+    events.iter().rev().find_map(|e| match e {
+        Event::Inventory(items) => Some(items.as_slice()),
+        _ => None,
+    })
+}
+
+// Codes in the events, in order.
+pub fn codes(events: &[Event]) -> impl Iterator<Item = &str> {
+    events.iter().filter_map(|e| match e {
+        Event::Code(code) => Some(code.as_str()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codes::codes_check::code_number;
+    use crate::vm::program::Program;
+    use crate::vm::Vm;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_room() {
+        let output = "\n\n== Foothills ==\nYou find yourself.\n\nThings of interest here:\n- tablet\n\nThere are 2 exits:\n- doorway\n- south\n\nWhat do you do?\n";
+        let events = parse(Some("look"), output);
+        assert_eq!(
+            events,
+            [
+                Event::Room("Foothills".to_string()),
+                Event::Description("You find yourself.".to_string()),
+                Event::Items(strings(&["tablet"])),
+                Event::Exits(strings(&["doorway", "south"])),
+                Event::Prompt,
+            ]
+        );
+        assert_eq!(
+            room(&events),
+            Some(RoomView {
+                name: "Foothills".to_string(),
+                description: "You find yourself.".to_string(),
+                items: strings(&["tablet"]),
+                exits: strings(&["doorway", "south"]),
+            })
+        );
+        assert_eq!(parse_room("Taken.\n\nWhat do you do?\n"), None);
+
+        let output = "== Passage ==\nA passage.\n\nIt is dark.\n\nThere is 1 exit:\n- back\n\nWhat do you do?\n";
+        let view = parse_room(output).unwrap();
+        assert_eq!(view.description, "A passage.\n\nIt is dark.");
+        assert_eq!(view.exits, ["back"]);
+    }
+
+    #[test]
+    fn test_parse_replies() {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        vm.run_actions(&[]);
+        let events = parse(None, &vm.take_output());
+        let numbers: Vec<_> = codes(&events).map(code_number).collect();
+        assert_eq!(numbers, [Some(1), Some(2)]);
+
+        let mut reply = |command| {
+            vm.run_actions(&[command]);
+            parse(Some(command), &vm.take_output())
+        };
+        assert_eq!(
+            reply("take tablet"),
+            [Event::Taken("tablet".to_string()), Event::Prompt]
+        );
+        assert_eq!(reply("inv")[0], Event::Inventory(strings(&["tablet"])));
+        assert_eq!(
+            reply("use lantern"),
+            [
+                Event::Text("You can't find that in your pack.".to_string()),
+                Event::Prompt
+            ]
+        );
+        let events = reply("use tablet");
+        assert!(matches!(&events[0], Event::Used { item, .. } if item == "tablet"));
+        let numbers: Vec<_> = codes(&events).map(code_number).collect();
+        assert_eq!(numbers, [Some(3)]);
+    }
+
+    #[test]
+    fn test_inline_codes() {
+        assert_eq!(
+            inline_codes("Through the mirror, you see \"abcdefABCDEF\" scrawled in charcoal."),
+            ["abcdefABCDEF"]
+        );
+        assert!(inline_codes("Through the mirror, you see \"nothing.\"").is_empty());
+    }
+}
@@ -1,18 +1,30 @@
-use crate::maze::teleporter_code::find_teleporter_code;
+use crate::maze::teleporter_code::{find_teleporter_code, TeleporterError};
+use crate::vm::assembler::assemble_patch;
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
 
+// Replaces the call to the check with noop, and changes the check to always pass:
+// r0 is still 4 as it was set before the call.
+const PATCH: &str = "
+5511    noop
+5512    noop
+5513    eq  r1  r0  4
+";
+
 // Patch the binary to allow to by-pass the teleporter check.
-pub fn patch(storage: &mut Storage) {
-    // Replace call with noop
-    storage.mem.write(5511, 21);
-    storage.mem.write(5512, 21);
+pub fn patch(storage: &mut Storage) -> Result<(), TeleporterError> {
+    patch_with_code(storage, find_teleporter_code()?);
+    println!("Teleported code patched!");
+    Ok(())
+}
 
-    // Change the check to always pass
-    storage.mem.write(5516, 4);
+// Same as patch, with a code already known, which avoids searching it again.
+pub fn patch_with_code(storage: &mut Storage, code: u16) {
+    let (start, words) = assemble_patch(PATCH).expect("Patch is valid");
+    for (a, word) in (start..).zip(words) {
+        storage.mem.write(a, word);
+    }
 
     // Set the register 8 to correct value
-    storage.regs.set(RegNb::new(7), find_teleporter_code());
-
-    println!("Teleported code patched!");
+    storage.regs.set(RegNb::new(7), code);
 }
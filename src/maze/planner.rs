@@ -0,0 +1,294 @@
+//! Finds the shortest list of commands reaching a target, by searching the states of the game.
+//!
+//! From each state, the planner tries every exit of the room, takes every item in it and uses
+//! every item of the inventory, driving the VM from a snapshot of the state. So preconditions,
+//! like a lit lantern to go through the darkness, don't need to be known: Without it, the
+//! game doesn't lead anywhere useful.
+//!
+//! States are told apart by the memory of the game, without the buffer of the last command:
+//! It includes where the player and the items are, and the state of the puzzles. The buffer
+//! depends on the binary, so it is found by probing the game before searching.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::vm::error::VmError;
+use crate::vm::machine::{ExitReason, Vm};
+use crate::vm::snapshot::Snapshot;
+
+use super::explorer::input_buffer;
+use super::parser::{self, parse, Event};
+
+const DEFAULT_MAX_STATES: usize = 200_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    // Be in a room with this name.
+    Room(String),
+    // Have this item in the inventory.
+    Item(String),
+    // Have the game print the code with this MD5, as lowercase hex.
+    Code(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Room(name) => write!(f, "room {}", name),
+            Target::Item(name) => write!(f, "item {}", name),
+            Target::Code(md5) => write!(f, "code {}", md5),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PlanError {
+    // All the reachable states were searched.
+    Unreachable(Target),
+    // Searching more states would be needed.
+    TooManyStates(Target, usize),
+    // What the game printed instead of a room.
+    NotInRoom(String),
+    Fault(VmError),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Unreachable(target) => write!(f, "Cannot reach {}", target),
+            PlanError::TooManyStates(target, n) => {
+                write!(f, "Did not reach {} in {} states", target, n)
+            }
+            PlanError::NotInRoom(output) => write!(f, "Not in a room: {:?}", output),
+            PlanError::Fault(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+// Whether the events announce the code with this MD5.
+fn has_code(events: &[Event], md5: &str) -> bool {
+    parser::codes(events).any(|c| format!("{:x}", md5::compute(c)) == md5)
+}
+
+// Runs one command, returning its output, or None if the game ended.
+fn run_command(vm: &mut Vm, command: &str) -> Result<Option<String>, PlanError> {
+    let reason = vm.run_actions(&[command]);
+    let output = vm.take_output();
+    match reason {
+        ExitReason::InputExhausted => Ok(Some(output)),
+        ExitReason::Halted => Ok(None),
+        ExitReason::Fault(e) => Err(PlanError::Fault(e)),
+    }
+}
+
+// A state to search, kept small: Its memory is stored as the words that differ from the
+// start.
+struct State {
+    snapshot: Snapshot,
+    changes: Vec<(usize, u16)>,
+    // Index of the state it comes from, with the command leading to it.
+    step: Option<(usize, String)>,
+}
+
+impl State {
+    fn new(vm: &Vm, start: &Snapshot, step: Option<(usize, String)>) -> Self {
+        let mut snapshot = vm.snapshot();
+        let changes = snapshot
+            .mem
+            .iter()
+            .zip(&start.mem)
+            .enumerate()
+            .filter(|(_, (w, s))| w != s)
+            .map(|(a, (w, _))| (a, *w))
+            .collect();
+        snapshot.mem = Vec::new();
+        Self {
+            snapshot,
+            changes,
+            step,
+        }
+    }
+
+    // The memory changes without the buffer of the last command, if known. The whole key is
+    // kept rather than a hash of it, so that different states are never mixed up.
+    fn key(&self, buffer: &Option<RangeInclusive<usize>>) -> Vec<(usize, u16)> {
+        self.changes
+            .iter()
+            .filter(|(a, _)| !buffer.as_ref().is_some_and(|b| b.contains(a)))
+            .copied()
+            .collect()
+    }
+
+    fn restore(&self, vm: &mut Vm, start: &Snapshot) {
+        let mut snapshot = self.snapshot.clone();
+        snapshot.mem = start.mem.clone();
+        for (a, w) in &self.changes {
+            snapshot.mem[*a] = *w;
+        }
+        vm.restore(&snapshot);
+    }
+}
+
+// Commands leading to the state, from the start.
+fn commands(states: &[State], mut index: usize) -> Vec<String> {
+    let mut commands = Vec::new();
+    while let Some((parent, command)) = &states[index].step {
+        commands.push(command.clone());
+        index = *parent;
+    }
+    commands.reverse();
+    commands
+}
+
+pub struct Planner {
+    // States to search before giving up.
+    pub max_states: usize,
+}
+
+impl Default for Planner {
+    fn default() -> Self {
+        Self {
+            max_states: DEFAULT_MAX_STATES,
+        }
+    }
+}
+
+impl Planner {
+    // Shortest list of commands reaching the target, from the state of the VM, waiting for a
+    // command. The VM is left in an unspecified state.
+    pub fn plan(&self, vm: &mut Vm, target: &Target) -> Result<Vec<String>, PlanError> {
+        // Diffs against the start are small once the game has set itself up.
+        if let ExitReason::Fault(e) = vm.run_actions(&[]) {
+            return Err(PlanError::Fault(e));
+        }
+        vm.take_output();
+        let buffer = input_buffer(vm).map_err(PlanError::Fault)?;
+        let start = vm.snapshot();
+        let mut states = vec![State::new(vm, &start, None)];
+        let mut seen = HashSet::from([states[0].key(&buffer)]);
+        let mut to_visit = VecDeque::from([0]);
+
+        while let Some(index) = to_visit.pop_front() {
+            states[index].restore(vm, &start);
+            let look = run_command(vm, "look")?.unwrap_or_default();
+            let room = parser::parse_room(&look).ok_or(PlanError::NotInRoom(look))?;
+            let inv = parse(Some("inv"), &run_command(vm, "inv")?.unwrap_or_default());
+            let inventory = parser::inventory(&inv).unwrap_or_default().to_vec();
+            match target {
+                Target::Room(name) if room.name == *name => return Ok(commands(&states, index)),
+                Target::Item(name) if inventory.contains(name) => {
+                    return Ok(commands(&states, index))
+                }
+                _ => {}
+            }
+
+            let next = room
+                .exits
+                .iter()
+                .map(|e| format!("go {}", e))
+                .chain(room.items.iter().map(|i| format!("take {}", i)))
+                .chain(inventory.iter().map(|i| format!("use {}", i)));
+            for command in next {
+                states[index].restore(vm, &start);
+                let Some(output) = run_command(vm, &command)? else {
+                    continue;
+                };
+                let found = matches!(target, Target::Code(md5)
+                    if has_code(&parse(Some(&command), &output), md5));
+                let state = State::new(vm, &start, Some((index, command)));
+                if found || seen.insert(state.key(&buffer)) {
+                    states.push(state);
+                    if found {
+                        return Ok(commands(&states, states.len() - 1));
+                    }
+                    if states.len() > self.max_states {
+                        return Err(PlanError::TooManyStates(target.clone(), self.max_states));
+                    }
+                    to_visit.push_back(states.len() - 1);
+                }
+            }
+        }
+        Err(PlanError::Unreachable(target.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maze::maze_commands::COMMANDS;
+    use crate::vm::program::Program;
+
+    fn plan(target: Target) -> Result<Vec<String>, PlanError> {
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        Planner::default().plan(&mut vm, &target)
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(plan(Target::Room("Foothills".to_string())), Ok(vec![]));
+        assert_eq!(
+            plan(Target::Item("lit lantern".to_string())).unwrap(),
+            [
+                "go doorway",
+                "go north",
+                "go north",
+                "go bridge",
+                "go continue",
+                "go down",
+                "go east",
+                "take empty lantern",
+                "go west",
+                "go west",
+                "go passage",
+                "go ladder",
+                "go west",
+                "go south",
+                "go north",
+                "take can",
+                "use can",
+                "use lantern",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_coins() {
+        // In front of the coin door, with the coins.
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        let at_door = COMMANDS.iter().position(|c| *c == "use blue coin").unwrap();
+        vm.run_actions(&COMMANDS[..at_door]);
+        let target = Target::Item("teleporter".to_string());
+        assert_eq!(
+            Planner::default().plan(&mut vm, &target).unwrap(),
+            [
+                "use blue coin",
+                "use red coin",
+                "use shiny coin",
+                "use concave coin",
+                "use corroded coin",
+                "go north",
+                "take teleporter",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_code() {
+        // The code written on the tablet.
+        let target = Target::Code("186f842951c0dcfe8838af1e7222b7d4".to_string());
+        assert_eq!(plan(target).unwrap(), ["take tablet", "use tablet"]);
+
+        let mut vm = Vm::new(&Program::challenge().unwrap());
+        let planner = Planner { max_states: 10 };
+        assert_eq!(
+            planner.plan(&mut vm, &Target::Item("orb".to_string())),
+            Err(PlanError::TooManyStates(
+                Target::Item("orb".to_string()),
+                10
+            ))
+        );
+    }
+}
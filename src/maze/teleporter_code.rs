@@ -1,64 +1,150 @@
-use rayon::prelude::*;
-
 // To use the teleporter, one has to both by-pass the check and find the correct code.
 // To find the correct code, we need to run the below function
-// (which actually implements the Ackermann function) on all values between 0 and 32767,
+// (which actually implements the Ackermann function) on all values between 1 and 32767,
 // and use the one that returns 6 (as 6 is the value the challenge checks it against).
 
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use rayon::prelude::*;
+
+// Value the check must return.
+const EXPECTED: u16 = 6;
+
+const UNSET: u16 = u16::MAX;
+
+#[derive(Debug, PartialEq)]
+pub enum TeleporterError {
+    // No value in the searched range passes the check.
+    NotFound(RangeInclusive<u16>),
+}
+
+impl fmt::Display for TeleporterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TeleporterError::NotFound(range) => write!(
+                f,
+                "No teleporter code between {} and {}",
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TeleporterError {}
+
+// What is left to do to compute fn6049.
+enum Task {
+    // Compute fn6049(a, b) into the result.
+    Call(u16, u16),
+    // Compute fn6049(a, result).
+    CallWithResult(u16),
+    // Save the result for this key.
+    Store(usize),
+}
+
 // Aka Ackermann function.
 // Initially, a = 4 and b = 1, as per the challenge input.
-// reg8 is where we are putting the values we are testing (0 to 32767).
+// reg8 is where we are putting the values we are testing (1 to 32767):
+//   fn6049(0, b) = b + 1
+//   fn6049(a, 0) = fn6049(a - 1, reg8)
+//   fn6049(a, b) = fn6049(a - 1, fn6049(a, b - 1))
 //
-// By default, this function will overflow its stack and be slow.
+// Called recursively, this function overflows its stack and is slow.
 //
 // We add a custom memoization method, borrowed from https://www.mattkeeter.com/blog/2024-01-28-synacor/
-// to prevents the stack overflow and make it faster.
-// The custom one that caches only a and b works much much faster than a generic based on memoize crate.
-fn fn6049(a: u16, b: u16, ret8: u16, seen: &mut [u16]) -> u16 {
+// to make it faster: The results are cached by a and b only, as reg8 doesn't change.
+// The recursion is replaced by a list of tasks, so any thread can run it with its default stack.
+fn fn6049(a: u16, b: u16, reg8: u16, seen: &mut [u16]) -> u16 {
     const MASK: u16 = 0x07FFF;
-    let key = (a as usize) | (b as usize) << 3;
-    if seen[key] == u16::MAX {
-        seen[key] = if a == 0 {
-            // Without wrapping, it produces only correct values for the smaller ones.
-            b.wrapping_add(1) & MASK
-        } else if b == 0 {
-            fn6049(a - 1, ret8, ret8, seen)
-        } else {
-            let t = fn6049(a, b - 1, ret8, seen);
-            fn6049(a - 1, t, ret8, seen)
-        };
+    let key = |a: u16, b: u16| (a as usize) | (b as usize) << 3;
+
+    let mut tasks = vec![Task::Call(a, b)];
+    let mut result = 0;
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Call(a, b) => {
+                let k = key(a, b);
+                if seen[k] != UNSET {
+                    result = seen[k];
+                } else if a == 0 {
+                    // Without wrapping, it produces only correct values for the smaller ones.
+                    result = b.wrapping_add(1) & MASK;
+                    seen[k] = result;
+                } else if b == 0 {
+                    tasks.push(Task::Store(k));
+                    tasks.push(Task::Call(a - 1, reg8));
+                } else {
+                    tasks.push(Task::Store(k));
+                    tasks.push(Task::CallWithResult(a - 1));
+                    tasks.push(Task::Call(a, b - 1));
+                }
+            }
+            Task::CallWithResult(a) => tasks.push(Task::Call(a, result)),
+            Task::Store(k) => seen[k] = result,
+        }
     }
-    seen[key]
+    result
 }
 
-#[test]
-fn test_fn6049() {
-    let mut seen = vec![u16::MAX; 1 << 18];
-    let result = fn6049(4, 1, 1, &mut seen);
-    assert_eq!(result, 32765);
+// Whether the check passes with this value in reg8.
+fn is_teleporter_code(reg8: u16) -> bool {
+    let mut seen = vec![UNSET; 1 << 18];
+    fn6049(4, 1, reg8, &mut seen) == EXPECTED
 }
 
-#[cfg(not(test))]
-pub fn find_teleporter_code() -> u16 {
-    // Rayon itself is recursive, so a bigger stack is needed
-    rayon::ThreadPoolBuilder::new()
-        .stack_size(8 * 1024 * 1024)
-        .build_global()
-        .unwrap();
+// All the values of the range that pass the check, in order.
+pub fn find_teleporter_codes(candidates: RangeInclusive<u16>) -> Result<Vec<u16>, TeleporterError> {
+    let codes: Vec<u16> = candidates
+        .clone()
+        .into_par_iter()
+        .filter(|r| is_teleporter_code(*r))
+        .collect();
+    if codes.is_empty() {
+        return Err(TeleporterError::NotFound(candidates));
+    }
+    Ok(codes)
+}
 
-    (1..32767)
+// The code to set in the 8th register. 0 means the teleporter isn't used, so it's not a code.
+// The search stops at the first one.
+pub fn find_teleporter_code() -> Result<u16, TeleporterError> {
+    (1..=32767)
         .into_par_iter()
-        .find_any(|r| {
-            let mut seen = vec![u16::MAX; 1 << 18];
-            let c = fn6049(4, 1, *r, &mut seen);
-            // println!("Trying {}: {}", r, c);
-            c == 6
-        })
-        .unwrap()
+        .find_first(|r| is_teleporter_code(*r))
+        .ok_or(TeleporterError::NotFound(1..=32767))
 }
 
-// For tests, hard-coding the results as the real method overflows its stack.
 #[cfg(test)]
-pub fn find_teleporter_code() -> u16 {
-    25734
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fn6049() {
+        let mut seen = vec![UNSET; 1 << 18];
+        let result = fn6049(4, 1, 1, &mut seen);
+        assert_eq!(result, 32765);
+
+        // The usual Ackermann function when reg8 is 1.
+        let mut seen = vec![UNSET; 1 << 18];
+        assert_eq!(fn6049(2, 3, 1, &mut seen), 9);
+    }
+
+    #[test]
+    fn test_find_codes() {
+        assert_eq!(find_teleporter_codes(25730..=25740), Ok(vec![25734]));
+        // Can be searched again.
+        assert_eq!(find_teleporter_codes(25734..=25734), Ok(vec![25734]));
+        assert_eq!(
+            find_teleporter_codes(1..=3),
+            Err(TeleporterError::NotFound(1..=3))
+        );
+    }
+
+    #[test]
+    fn test_find_code() {
+        // The whole search, shared with the tests patching the teleporter.
+        assert_eq!(crate::codes::teleporter_code(), 25734);
+    }
 }
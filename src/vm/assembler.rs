@@ -0,0 +1,404 @@
+//! Assembler, reading the same syntax the decompiler writes.
+//!
+//! ```text
+//! ; Comments start with a semicolon.
+//! start:
+//!     set r0 'A'          ; Registers are r0-r7, characters are quoted.
+//! loop:
+//!     out r0
+//!     add r0 r0 1
+//!     eq r1 r0 'Z'
+//!     jf r1 loop
+//!     halt
+//! 100 .data 1 2 start     ; A leading number is the address: The image is padded up to it.
+//!     .string "hello\n"   ; One word per character.
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::instructions::{decode, Instruction, BUILDERS};
+use super::program::{ADDRESS_SPACE, FIRST_REGISTER, MAX_VALID_WORD};
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    // Line of the source, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Something that ends up as words in the image.
+enum Item {
+    // Opcode and operands.
+    Instruction(u16, Vec<String>),
+    Data(Vec<String>),
+    String(String),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instruction(_, operands) => 1 + operands.len(),
+            Item::Data(values) => values.len(),
+            Item::String(s) => s.chars().count(),
+        }
+    }
+}
+
+// Mnemonic to opcode and number of operands, taken from the instructions themselves.
+fn mnemonics() -> HashMap<&'static str, (u16, usize)> {
+    (0..BUILDERS.len() as u16)
+        .map(|opcode| {
+            // Registers are accepted by every operand.
+            let ins = decode(0, &[opcode, FIRST_REGISTER, FIRST_REGISTER, FIRST_REGISTER])
+                .expect("Instruction accepts registers");
+            (ins.name(), (opcode, ins.offset() as usize - 1))
+        })
+        .collect()
+}
+
+// Splits a line into tokens, keeping quoted characters and strings whole and dropping comments.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() || c == ',' => {}
+            '\'' | '"' => {
+                let mut token = c.to_string();
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            token.push('\\');
+                            token.push(chars.next().ok_or("Unterminated escape")?);
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => token.push(other),
+                        None => return Err(format!("Missing closing {}", c)),
+                    }
+                }
+                token.push(c);
+                tokens.push(token);
+            }
+            _ => {
+                let mut token = c.to_string();
+                while let Some(next) =
+                    chars.next_if(|n| !n.is_whitespace() && *n != ',' && *n != ';')
+                {
+                    token.push(next);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Text between the quotes, with escapes resolved.
+fn unquote(token: &str) -> Result<String, String> {
+    let inner = &token[1..token.len() - 1];
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('0') => out.push('\0'),
+            Some(e @ ('\\' | '\'' | '"')) => out.push(e),
+            Some(e) => return Err(format!("Unknown escape \\{}", e)),
+            None => return Err("Unterminated escape".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Value of an operand: Number, register, character or label.
+fn operand_value(token: &str, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    if let Some(r) = token.strip_prefix('r') {
+        if let Ok(r) = r.parse::<u16>() {
+            return if r <= 7 {
+                Ok(FIRST_REGISTER + r)
+            } else {
+                Err(format!("Invalid register {}", token))
+            };
+        }
+    }
+    if token.starts_with('\'') {
+        let s = unquote(token)?;
+        let mut chars = s.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if (c as u32) < FIRST_REGISTER as u32 => Ok(c as u16),
+            _ => Err(format!("Invalid character {}", token)),
+        };
+    }
+    if let Ok(n) = token.parse::<u16>() {
+        return if n <= MAX_VALID_WORD {
+            Ok(n)
+        } else {
+            Err(format!("Invalid number {}", token))
+        };
+    }
+    if is_label(token) {
+        return labels
+            .get(token)
+            .copied()
+            .ok_or_else(|| format!("Undefined label {}", token));
+    }
+    Err(format!("Invalid operand {}", token))
+}
+
+fn define_labels(
+    labels: &mut HashMap<String, u16>,
+    pending_labels: &mut Vec<(usize, String)>,
+    address: usize,
+) -> Result<(), AsmError> {
+    for (line_nb, label) in pending_labels.drain(..) {
+        if labels.insert(label.clone(), address as u16).is_some() {
+            return Err(AsmError::new(line_nb, format!("Duplicate label {}", label)));
+        }
+    }
+    Ok(())
+}
+
+// Assembles the source. Returns the address of the first word and the words from there,
+// which is what a patch of an existing image needs.
+pub fn assemble_patch(source: &str) -> Result<(u16, Vec<u16>), AsmError> {
+    let mnemonics = mnemonics();
+    let mut labels: HashMap<String, u16> = HashMap::new();
+    // Line, address and item.
+    let mut items: Vec<(usize, usize, Item)> = Vec::new();
+    let mut start = None;
+    let mut address = 0;
+    // Labels waiting for the address of the next item, with their line.
+    let mut pending_labels: Vec<(usize, String)> = Vec::new();
+
+    // First pass: Addresses of the items and labels.
+    for (i, line) in source.lines().enumerate() {
+        let line_nb = i + 1;
+        let err = |message: String| AsmError::new(line_nb, message);
+        let mut tokens = tokenize(line).map_err(err)?;
+
+        let origin = match tokens.first().map(|t| t.parse::<usize>()) {
+            Some(Ok(origin)) => {
+                tokens.remove(0);
+                Some(origin)
+            }
+            _ => None,
+        };
+        while let Some(label) = tokens.first().and_then(|t| t.strip_suffix(':')) {
+            if !is_label(label) {
+                return Err(err(format!("Invalid label {}", label)));
+            }
+            pending_labels.push((line_nb, label.to_string()));
+            tokens.remove(0);
+        }
+        if tokens.is_empty() {
+            if origin.is_some() {
+                return Err(err("Nothing at this address".to_string()));
+            }
+            continue;
+        }
+
+        if let Some(origin) = origin {
+            if origin < address {
+                return Err(err(format!(
+                    "Address {} is before the current address {}",
+                    origin, address
+                )));
+            }
+            address = origin;
+        }
+        start.get_or_insert(address);
+        define_labels(&mut labels, &mut pending_labels, address)?;
+
+        let item = match tokens[0].as_str() {
+            ".data" => {
+                if tokens.len() < 2 {
+                    return Err(err(".data needs values".to_string()));
+                }
+                Item::Data(tokens[1..].to_vec())
+            }
+            ".string" => match &tokens[1..] {
+                [s] if s.starts_with('"') => Item::String(unquote(s).map_err(err)?),
+                _ => return Err(err(".string needs a quoted string".to_string())),
+            },
+            name => {
+                let (opcode, args) = *mnemonics
+                    .get(name)
+                    .ok_or_else(|| err(format!("Unknown instruction {}", name)))?;
+                if tokens.len() - 1 != args {
+                    return Err(err(format!(
+                        "{} takes {} operands, got {}",
+                        name,
+                        args,
+                        tokens.len() - 1
+                    )));
+                }
+                Item::Instruction(opcode, tokens[1..].to_vec())
+            }
+        };
+        let len = item.len();
+        items.push((line_nb, address, item));
+        address += len;
+        if address > ADDRESS_SPACE {
+            return Err(err("Program is larger than the address space".to_string()));
+        }
+    }
+
+    define_labels(&mut labels, &mut pending_labels, address)?;
+
+    // Second pass: Operands, now that all labels are known.
+    let start = start.unwrap_or(0);
+    let mut words = Vec::new();
+    for (line_nb, address, item) in items {
+        let err = |message: String| AsmError::new(line_nb, message);
+        words.resize(address - start, 0);
+        match item {
+            Item::Instruction(opcode, operands) => {
+                words.push(opcode);
+                for operand in operands {
+                    words.push(operand_value(&operand, &labels).map_err(err)?);
+                }
+                // Let the instruction check its operands, like a register where one is needed.
+                let mut ins_words = words[address - start..].to_vec();
+                ins_words.resize(4, 0);
+                decode(address as u16, &ins_words).map_err(|e| err(e.to_string()))?;
+            }
+            Item::Data(values) => {
+                for value in values {
+                    words.push(operand_value(&value, &labels).map_err(err)?);
+                }
+            }
+            Item::String(s) => {
+                for c in s.chars() {
+                    // Like a character operand, it must be a literal value.
+                    if c as u32 >= FIRST_REGISTER as u32 {
+                        return Err(err(format!("Invalid character {:?} in string", c)));
+                    }
+                    words.push(c as u16);
+                }
+            }
+        }
+    }
+    Ok((start as u16, words))
+}
+
+// Assembles the source into an image starting at address 0.
+pub fn assemble(source: &str) -> Result<Vec<u16>, AsmError> {
+    let (start, mut words) = assemble_patch(source)?;
+    words.splice(0..0, std::iter::repeat_n(0, start as usize));
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::decompiler::disassemble;
+    use crate::vm::machine::{ExitReason, Vm};
+    use crate::vm::program::Program;
+
+    #[test]
+    fn test_assemble() {
+        let source = r#"
+            ; Prints the alphabet
+            start:  set r0 'A'
+            loop:   out r0
+                    add r0, r0, 1
+                    gt r1 r0 'Z'
+                    jf r1 loop
+                    out '\n'
+                    halt
+            20 msg: .string "ok"
+                    .data 1 r7 start ' '
+        "#;
+        let words = assemble(source).unwrap();
+        assert_eq!(words[21..], [107, 1, 32775, 0, 32]);
+        assert_eq!(
+            words[..21],
+            [
+                1, 32768, 65, 19, 32768, 9, 32768, 32768, 1, 5, 32769, 32768, 90, 8, 32769, 3, 19,
+                10, 0, 0, 111
+            ]
+        );
+        let mut vm = Vm::new(&Program::from_words(words).unwrap());
+        assert_eq!(vm.run_until_input(), ExitReason::Halted);
+        assert_eq!(vm.take_output(), "ABCDEFGHIJKLMNOPQRSTUVWXYZ\n");
+    }
+
+    #[test]
+    fn test_data() {
+        let words = assemble("10 a: .data 1 a 'x'\n  .string \"hi\\n\"\nb: .data b").unwrap();
+        assert_eq!(words.len(), 17);
+        assert_eq!(words[10..], [1, 10, 120, 104, 105, 10, 16]);
+    }
+
+    #[test]
+    fn test_patch() {
+        let (start, words) = assemble_patch("5511 noop\n5512 noop\n5513 eq r1 r0 4").unwrap();
+        assert_eq!(start, 5511);
+        assert_eq!(words, [21, 21, 4, 32769, 32768, 4]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| assemble(source).unwrap_err();
+        assert_eq!(
+            error("noop\nfoo r0"),
+            AsmError::new(2, "Unknown instruction foo".to_string())
+        );
+        assert_eq!(error("out").line, 1);
+        assert_eq!(error("jmp nowhere").message, "Undefined label nowhere");
+        assert_eq!(error("set 1 2").message, "Invalid register 1");
+        assert_eq!(error("out r8").message, "Invalid register r8");
+        assert_eq!(error("a: noop\na: noop").message, "Duplicate label a");
+        assert_eq!(error("10 noop\n5 noop").line, 2);
+        assert_eq!(error("out 'ab'").message, "Invalid character 'ab'");
+        assert_eq!(error(".string \"abc").message, "Missing closing \"");
+        assert_eq!(error(".data 32776").message, "Invalid number 32776");
+        assert_eq!(error(".data 65535").message, "Invalid number 65535");
+        assert_eq!(error("out 40000").message, "Invalid number 40000");
+        assert_eq!(
+            error(".string \"a\u{8000}\"").message,
+            "Invalid character '\u{8000}' in string"
+        );
+        assert_eq!(
+            error(".string \"\u{1F600}\"").message,
+            "Invalid character '😀' in string"
+        );
+        assert!(assemble(".data 32775").is_ok());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = Program::challenge().unwrap();
+        let words = assemble(&disassemble(&program)).unwrap();
+        assert_eq!(words, program.words());
+    }
+}
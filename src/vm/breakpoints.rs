@@ -0,0 +1,407 @@
+//! Breakpoints and watchpoints of the debugger.
+
+use std::fmt;
+
+use crate::vm::program::is_address;
+use crate::vm::register::RegNb;
+use crate::vm::storage::Storage;
+
+// Parses a memory address, rejecting numbers outside of the address space.
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    match s.parse::<u16>() {
+        Ok(a) if is_address(a) => Ok(a),
+        Ok(a) => Err(format!("Invalid address {}, must be below 32768", a)),
+        Err(_) => Err(format!("Invalid address {}", s)),
+    }
+}
+
+// A value that can be used in conditions: r0-r7, [address] for memory, or a literal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Reg(RegNb),
+    Mem(u16),
+    Value(u16),
+}
+
+impl Operand {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Some(r) = s.strip_prefix('r') {
+            if let Ok(r) = r.parse::<usize>() {
+                if RegNb::is_valid(r) {
+                    return Ok(Operand::Reg(RegNb::new(r)));
+                }
+            }
+        }
+        if let Some(a) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            return parse_address(a).map(Operand::Mem);
+        }
+        s.parse::<u16>()
+            .map(Operand::Value)
+            .map_err(|_| format!("Invalid operand {}", s))
+    }
+
+    fn eval(&self, storage: &Storage) -> u16 {
+        match self {
+            Operand::Reg(r) => storage.regs.to_array()[**r],
+            Operand::Mem(a) => storage.mem.read(*a),
+            Operand::Value(v) => *v,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "{}", r),
+            Operand::Mem(a) => write!(f, "[{}]", a),
+            Operand::Value(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn sign(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+}
+
+// Condition of a breakpoint, like "r7 == 25734".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    lhs: Operand,
+    op: CmpOp,
+    rhs: Operand,
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<_> = s.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("Condition must be <a> <op> <b>, got '{}'", s));
+        }
+        let op = match parts[1] {
+            "==" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            ">=" => CmpOp::Ge,
+            o => return Err(format!("Invalid comparison {}", o)),
+        };
+        Ok(Self {
+            lhs: Operand::parse(parts[0])?,
+            op,
+            rhs: Operand::parse(parts[2])?,
+        })
+    }
+
+    pub fn eval(&self, storage: &Storage) -> bool {
+        let a = self.lhs.eval(storage);
+        let b = self.rhs.eval(storage);
+        match self.op {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op.sign(), self.rhs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+    pub enabled: bool,
+    pub condition: Option<Condition>,
+    pub hits: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchTarget {
+    Mem(u16),
+    Reg(RegNb),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    // Stop when the value changes.
+    Change,
+    // Stop on any write, even if it writes the same value. Only for memory.
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub target: WatchTarget,
+    pub kind: WatchKind,
+    pub enabled: bool,
+    pub hits: usize,
+    last_value: u16,
+}
+
+impl Watchpoint {
+    fn value(&self, storage: &Storage) -> u16 {
+        match self.target {
+            WatchTarget::Mem(a) => storage.mem.read(a),
+            WatchTarget::Reg(r) => storage.regs.to_array()[*r],
+        }
+    }
+}
+
+// All breakpoints and watchpoints, sharing the same ids.
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+}
+
+impl Breakpoints {
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16, condition: Option<Condition>) -> usize {
+        let id = self.new_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            enabled: true,
+            condition,
+            hits: 0,
+        });
+        id
+    }
+
+    pub fn add_watchpoint(
+        &mut self,
+        target: WatchTarget,
+        kind: WatchKind,
+        storage: &Storage,
+    ) -> usize {
+        let id = self.new_id();
+        let mut watchpoint = Watchpoint {
+            id,
+            target,
+            kind,
+            enabled: true,
+            hits: 0,
+            last_value: 0,
+        };
+        watchpoint.last_value = watchpoint.value(storage);
+        self.watchpoints.push(watchpoint);
+        id
+    }
+
+    // Returns false if there is no such id.
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            bp.enabled = enabled;
+            return true;
+        }
+        if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            wp.enabled = enabled;
+            return true;
+        }
+        false
+    }
+
+    // Returns false if there is no such id.
+    pub fn delete(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|bp| bp.id != id);
+        self.watchpoints.retain(|wp| wp.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    // The enabled breakpoint at this address whose condition holds, if any.
+    pub fn matching(&self, ir: u16, storage: &Storage) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|bp| {
+            bp.enabled && bp.address == ir && bp.condition.is_none_or(|c| c.eval(storage))
+        })
+    }
+
+    // To call before executing the instruction at ir: Returns the breakpoint that was hit.
+    pub fn check_breakpoint(&mut self, ir: u16, storage: &Storage) -> Option<&Breakpoint> {
+        let id = self.matching(ir, storage)?.id;
+        let bp = self.breakpoints.iter_mut().find(|bp| bp.id == id).unwrap();
+        bp.hits += 1;
+        Some(bp)
+    }
+
+    // To call after executing an instruction, with the memory write it did.
+    // Returns a message for each watchpoint that was hit.
+    pub fn check_watchpoints(
+        &mut self,
+        storage: &Storage,
+        last_write: Option<(u16, u16)>,
+    ) -> Vec<String> {
+        let mut hits = Vec::new();
+        for wp in self.watchpoints.iter_mut() {
+            let value = wp.value(storage);
+            let written =
+                matches!((wp.target, last_write), (WatchTarget::Mem(a), Some((w, _))) if a == w);
+            let hit = match wp.kind {
+                WatchKind::Change => value != wp.last_value,
+                WatchKind::Write => written,
+            };
+            if wp.enabled && hit {
+                wp.hits += 1;
+                hits.push(format!(
+                    "Watchpoint {} hit: {} {} -> {}",
+                    wp.id,
+                    target_str(&wp.target),
+                    wp.last_value,
+                    value
+                ));
+            }
+            wp.last_value = value;
+        }
+        hits
+    }
+}
+
+fn target_str(target: &WatchTarget) -> String {
+    match target {
+        WatchTarget::Mem(a) => format!("mem {}", a),
+        WatchTarget::Reg(r) => r.to_string(),
+    }
+}
+
+impl fmt::Display for Breakpoints {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            return writeln!(f, "No breakpoints");
+        }
+        for bp in &self.breakpoints {
+            write!(f, "{}\tbp {}", bp.id, bp.address)?;
+            if let Some(c) = bp.condition {
+                write!(f, " if {}", c)?;
+            }
+            writeln!(
+                f,
+                "\t{}\thits: {}",
+                if bp.enabled { "enabled" } else { "disabled" },
+                bp.hits
+            )?;
+        }
+        for wp in &self.watchpoints {
+            writeln!(
+                f,
+                "{}\twatch {}{}\t{}\thits: {}",
+                wp.id,
+                target_str(&wp.target),
+                if wp.kind == WatchKind::Write {
+                    " write"
+                } else {
+                    ""
+                },
+                if wp.enabled { "enabled" } else { "disabled" },
+                wp.hits
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        let mut storage = Storage::new();
+        let c = Condition::parse("r7 == 25734").unwrap();
+        assert_eq!(c.to_string(), "r7 == 25734");
+        assert!(!c.eval(&storage));
+        storage.regs.set(RegNb::new(7), 25734);
+        assert!(c.eval(&storage));
+
+        storage.mem.write(3952, 10);
+        assert!(Condition::parse("[3952] >= r0").unwrap().eval(&storage));
+        assert!(!Condition::parse("[3952] < 10").unwrap().eval(&storage));
+
+        assert!(Condition::parse("r8 == 1").is_err());
+        assert!(Condition::parse("r1 = 1").is_err());
+        assert!(Condition::parse("r1 ==").is_err());
+        assert!(Condition::parse("[40000] == 0").is_err());
+        assert!(Condition::parse("[32768] == 0").is_err());
+        assert!(Condition::parse("[abc] == 0").is_err());
+        assert!(Condition::parse("[32767] == 0").is_ok());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut storage = Storage::new();
+        let mut bps = Breakpoints::default();
+        let id1 = bps.add_breakpoint(6049, Some(Condition::parse("r7 == 3").unwrap()));
+        let id2 = bps.add_breakpoint(100, None);
+
+        assert!(bps.check_breakpoint(6049, &storage).is_none());
+        storage.regs.set(RegNb::new(7), 3);
+        assert_eq!(bps.check_breakpoint(6049, &storage).unwrap().id, id1);
+        assert_eq!(bps.check_breakpoint(6049, &storage).unwrap().hits, 2);
+
+        assert!(bps.set_enabled(id2, false));
+        assert!(bps.check_breakpoint(100, &storage).is_none());
+        assert!(bps.set_enabled(id2, true));
+        assert!(bps.check_breakpoint(100, &storage).is_some());
+
+        assert!(bps.delete(id1));
+        assert!(!bps.delete(id1));
+        assert!(bps.check_breakpoint(6049, &storage).is_none());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut storage = Storage::new();
+        let mut bps = Breakpoints::default();
+        bps.add_watchpoint(WatchTarget::Reg(RegNb::new(7)), WatchKind::Change, &storage);
+        bps.add_watchpoint(WatchTarget::Mem(3952), WatchKind::Write, &storage);
+
+        assert!(bps.check_watchpoints(&storage, None).is_empty());
+
+        storage.regs.set(RegNb::new(7), 1);
+        assert_eq!(bps.check_watchpoints(&storage, None).len(), 1);
+        // Already reported.
+        assert!(bps.check_watchpoints(&storage, None).is_empty());
+
+        // Writing the same value still triggers a write watchpoint.
+        let val = storage.mem.read(3952);
+        storage.mem.write(3952, val);
+        let write = storage.mem.take_last_write();
+        assert_eq!(bps.check_watchpoints(&storage, write).len(), 1);
+    }
+}
@@ -1,24 +1,43 @@
-use crate::vm::instructions::is_opcode;
-
-use super::instructions::get_instruction;
+use super::breakpoints::{parse_address, Breakpoints, Condition, WatchKind, WatchTarget};
+use super::instructions::{get_instruction, Instruction};
 use super::register::RegNb;
 use super::storage::Storage;
 
+// Changes to the breakpoints and watchpoints.
+pub enum BreakpointCmd {
+    Add(u16, Option<Condition>),
+    Watch(WatchTarget, WatchKind),
+    Enable(usize),
+    Disable(usize),
+    Delete(usize),
+    Clear,
+}
+
 // Actions that the debugger may set and that need to be used by the runner.
 #[derive(Default)]
 pub struct DebuggerActions {
     pub quit: Option<bool>,
     pub verbose: Option<bool>,
-    pub set_breakpoint: Option<u16>,
-    pub clear_breakpoint: Option<bool>,
+    pub breakpoint: Option<BreakpointCmd>,
     pub set_register: Option<(RegNb, u16)>,
     pub set_memory: Option<(u16, u16)>,
+    pub save_state: Option<String>,
+    pub load_state: Option<String>,
+    pub step: Option<usize>,
+    pub step_back: Option<usize>,
+    pub reverse_continue: Option<bool>,
+    pub history_capacity: Option<usize>,
 }
 
 // Executes the debugger command.
 // This function doesn't modify the state of the program directly, but if it needs to,
 // it indicates it via the returned actions.
-pub fn exec_debug_cmd(s: &str, ir: u16, storage: &Storage) -> DebuggerActions {
+pub fn exec_debug_cmd(
+    s: &str,
+    ir: u16,
+    storage: &Storage,
+    breakpoints: &Breakpoints,
+) -> DebuggerActions {
     if s.is_empty() {
         return DebuggerActions {
             ..Default::default()
@@ -41,15 +60,16 @@ pub fn exec_debug_cmd(s: &str, ir: u16, storage: &Storage) -> DebuggerActions {
             if parts.len() < 2 {
                 return DebuggerActions::default();
             }
-            if let Ok(address) = parts[1].parse::<u16>() {
-                println!("[{}] {}", address, storage.mem.read(address));
+            match parse_address(parts[1]) {
+                Ok(address) => println!("[{}] {}", address, storage.mem.read(address)),
+                Err(e) => println!("{}. Usage: print a", e),
             }
         }
         "show" => {
             if parts.len() < 2 {
                 return DebuggerActions::default();
             }
-            if let Ok(address) = parts[1].parse::<u16>() {
+            if let Ok(address) = parse_address(parts[1]) {
                 let n = parts.get(2).unwrap_or(&"1").parse::<u16>().unwrap_or(1);
                 show_n_instructions(address, n, storage);
             }
@@ -58,7 +78,7 @@ pub fn exec_debug_cmd(s: &str, ir: u16, storage: &Storage) -> DebuggerActions {
             let verbose = parts.len() > 1 && parts[1] == "on";
             println!("Verbose mode {}", if verbose { "ON" } else { "OFF" });
             return DebuggerActions {
-                verbose: Some(true),
+                verbose: Some(verbose),
                 ..Default::default()
             };
         }
@@ -66,16 +86,77 @@ pub fn exec_debug_cmd(s: &str, ir: u16, storage: &Storage) -> DebuggerActions {
             if parts.len() < 2 {
                 return DebuggerActions::default();
             }
-            if let Ok(address) = parts[1].parse::<u16>() {
+            let address = match parse_address(parts[1]) {
+                Ok(address) => address,
+                Err(e) => {
+                    println!("{}. Usage: bp a [if cond]", e);
+                    return DebuggerActions::default();
+                }
+            };
+            let condition = match parts.get(2) {
+                None => None,
+                Some(&"if") => match Condition::parse(&parts[3..].join(" ")) {
+                    Ok(c) => Some(c),
+                    Err(e) => {
+                        println!("{}", e);
+                        return DebuggerActions::default();
+                    }
+                },
+                Some(_) => {
+                    println!("Usage: bp a [if cond]");
+                    return DebuggerActions::default();
+                }
+            };
+            return DebuggerActions {
+                breakpoint: Some(BreakpointCmd::Add(address, condition)),
+                ..Default::default()
+            };
+        }
+        "watch" => {
+            let target = match (parts.get(1), parts.get(2)) {
+                (Some(&"mem"), Some(a)) => parse_address(a).ok().map(WatchTarget::Mem),
+                (Some(r), None) => r
+                    .strip_prefix('r')
+                    .and_then(|r| r.parse::<usize>().ok())
+                    .filter(|r| RegNb::is_valid(*r))
+                    .map(|r| WatchTarget::Reg(RegNb::new(r))),
+                _ => None,
+            };
+            let kind = match parts.get(3) {
+                None => Some(WatchKind::Change),
+                Some(&"write") => Some(WatchKind::Write),
+                Some(_) => None,
+            };
+            if let (Some(target), Some(kind)) = (target, kind) {
+                return DebuggerActions {
+                    breakpoint: Some(BreakpointCmd::Watch(target, kind)),
+                    ..Default::default()
+                };
+            }
+            println!("Usage: watch mem a [write], or watch r");
+        }
+        "info" | "bps" => {
+            print!("{}", breakpoints);
+        }
+        "enable" | "disable" | "delete" => {
+            if parts.len() < 2 {
+                return DebuggerActions::default();
+            }
+            if let Ok(id) = parts[1].parse::<usize>() {
+                let cmd = match parts[0] {
+                    "enable" => BreakpointCmd::Enable(id),
+                    "disable" => BreakpointCmd::Disable(id),
+                    _ => BreakpointCmd::Delete(id),
+                };
                 return DebuggerActions {
-                    set_breakpoint: Some(address),
+                    breakpoint: Some(cmd),
                     ..Default::default()
                 };
             }
         }
         "clearbp" => {
             return DebuggerActions {
-                clear_breakpoint: Some(true),
+                breakpoint: Some(BreakpointCmd::Clear),
                 ..Default::default()
             };
         }
@@ -98,15 +179,69 @@ pub fn exec_debug_cmd(s: &str, ir: u16, storage: &Storage) -> DebuggerActions {
             if parts.len() < 3 {
                 return DebuggerActions::default();
             }
-            if let Ok(address) = parts[1].parse::<u16>() {
-                if let Ok(val) = parts[2].parse::<u16>() {
-                    return DebuggerActions {
-                        set_memory: Some((address, val)),
-                        ..Default::default()
-                    };
+            let address = match parse_address(parts[1]) {
+                Ok(address) => address,
+                Err(e) => {
+                    println!("{}. Usage: setm a val", e);
+                    return DebuggerActions::default();
                 }
+            };
+            if let Ok(val) = parts[2].parse::<u16>() {
+                return DebuggerActions {
+                    set_memory: Some((address, val)),
+                    ..Default::default()
+                };
+            }
+        }
+        "step" | "s" => {
+            let n = parts.get(1).unwrap_or(&"1").parse::<usize>().unwrap_or(1);
+            return DebuggerActions {
+                step: Some(n),
+                ..Default::default()
+            };
+        }
+        "back" | "rstep" => {
+            let n = parts.get(1).unwrap_or(&"1").parse::<usize>().unwrap_or(1);
+            return DebuggerActions {
+                step_back: Some(n),
+                ..Default::default()
+            };
+        }
+        "reverse-continue" | "rc" => {
+            return DebuggerActions {
+                reverse_continue: Some(true),
+                ..Default::default()
+            };
+        }
+        "history" => {
+            if parts.len() < 2 {
+                return DebuggerActions::default();
+            }
+            if let Ok(n) = parts[1].parse::<usize>() {
+                return DebuggerActions {
+                    history_capacity: Some(n),
+                    ..Default::default()
+                };
             }
         }
+        "save" => {
+            if parts.len() < 2 {
+                return DebuggerActions::default();
+            }
+            return DebuggerActions {
+                save_state: Some(parts[1].to_string()),
+                ..Default::default()
+            };
+        }
+        "load" => {
+            if parts.len() < 2 {
+                return DebuggerActions::default();
+            }
+            return DebuggerActions {
+                load_state: Some(parts[1].to_string()),
+                ..Default::default()
+            };
+        }
         "q" | "quit" => {
             println!("Quitting debugger");
             return DebuggerActions {
@@ -124,10 +259,23 @@ stack       Show stack.
 print a     Prints value at address <a>.
 show a n    Displays <n> instruction at address <a>.
 verbose [on|off] Turns verbose mode on/off.
-bp a        Set breakpoint at address <a>.
-clearbp     Clear breakpoint.
+bp a [if c] Set breakpoint at address <a>, stopping only if <c> holds, like: bp 6049 if r7 == 25734.
+            Conditions compare registers r0-r7, memory [a] or numbers with == != < <= > >=.
+watch mem a [write]  Stop when memory at <a> changes, or on any write to it.
+watch r     Stop when register <r> changes, like: watch r7.
+info        List breakpoints and watchpoints with their ids and hit counts, also bps.
+enable id   Enable breakpoint or watchpoint <id>.
+disable id  Disable breakpoint or watchpoint <id>.
+delete id   Delete breakpoint or watchpoint <id>.
+clearbp     Delete all breakpoints and watchpoints.
 setr r val  Set register <r> to <val>.
 setm a val  Set memory address <a> to <val>.
+step [n]    Execute <n> instructions (1 by default).
+back [n]    Undo the last <n> instructions (1 by default), also rstep.
+reverse-continue  Run backwards until a breakpoint, also rc.
+history n   Keep the last <n> instructions for going backwards.
+save file   Save the state of the VM to <file>.
+load file   Restore the state of the VM from <file>.
 quit        Quit debugger.
 "
             );
@@ -140,11 +288,15 @@ quit        Quit debugger.
 fn show_n_instructions(address: u16, n: u16, storage: &Storage) {
     let mut a = address;
     for _ in 0..n {
-        if !is_opcode(storage.mem.read(address)) {
-            return;
+        match get_instruction(storage, a) {
+            Ok(ins) => {
+                println!("[{}] {}", a, ins);
+                a += ins.offset();
+            }
+            Err(e) => {
+                println!("[{}] {}", a, e.kind);
+                return;
+            }
         }
-        let ins = get_instruction(storage, a);
-        println!("[{}] {}", a, ins);
-        a += ins.offset();
     }
 }
@@ -1,33 +1,256 @@
 //! Tool to decompile the binary, to help with reverse-engineering it.
+//!
+//! Code is found by following the control flow from address 0: Literal targets of `jmp`, `jt`, `jf`
+//! and `call` are decoded too, and get a label. Everything that isn't reached that way is data.
+//! Targets outside the program or in the middle of an instruction stay numbers, with a comment.
+//!
+//! The challenge decrypts part of its memory and patches its own code after the self-test, and
+//! most of the game is called through function pointers. So `decompile` first runs the program,
+//! recording where the indirect calls and jumps go, and uses these as extra entry points.
 
 // Format:
-// 2022   eq  reg[4]  reg[2]   10000
+// fn_6049:
+// 6049	jt	r0	loc_6057
+// 2022	eq	r4	r2	10000
+// 6090	.data	30
 
-use crate::vm::instructions::BUILDERS;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use super::instructions::is_opcode;
+use super::instructions::{decode, Instruction};
+use super::intreg::IntReg;
+use super::machine::{ExitReason, Vm};
+use super::program::Program;
+use super::register::RegNb;
 use super::storage::Memory;
 
-#[allow(dead_code)]
-pub fn decompile() {
-    let mem = Memory::new();
-
-    let mut address = 0;
-    while address < mem.len() {
-        let opcode = mem.read(address);
-        // All the code seems to be before 6090
-        if address < 6090 && is_opcode(opcode) {
-            let ins = BUILDERS[opcode as usize](address, mem.ins_slice(address));
-            println!("{}", ins.decompile());
-            address += ins.offset();
-        } else {
-            // TODO add instruction validation to the instruction instance methods.
-
-            // if is_opcode(opcode) {
-            //     println!("Possible opcode: {}\t{}", address, mem.read(address));
-            // }
-            println!("{}\t{}", address, mem.read(address));
-            address += 1;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Label {
+    // Target of a call.
+    Function,
+    // Target of a jump.
+    Location,
+}
+
+fn label_name(address: u16, label: Label) -> String {
+    match label {
+        Label::Function => format!("fn_{}", address),
+        Label::Location => format!("loc_{}", address),
+    }
+}
+
+// Result of following the control flow.
+struct Analysis {
+    // Addresses where a reachable instruction starts.
+    instructions: BTreeSet<u16>,
+    labels: HashMap<u16, Label>,
+}
+
+fn analyze(mem: &Memory, entry_points: &[(u16, Option<Label>)]) -> Analysis {
+    let mut instructions = BTreeSet::new();
+    let mut labels: HashMap<u16, Label> = entry_points
+        .iter()
+        .filter_map(|(a, label)| label.map(|l| (*a, l)))
+        .collect();
+
+    // Addresses to decode, with the constant loaded into a register just before, if any.
+    let mut to_visit: Vec<(u16, Option<(RegNb, u16)>)> =
+        entry_points.iter().map(|(a, _)| (*a, None)).collect();
+    while let Some((address, loaded)) = to_visit.pop() {
+        if address as usize >= mem.len() || instructions.contains(&address) {
+            continue;
+        }
+        // Execution would fault there, so the path stops.
+        let Ok(ins) = decode(address, mem.ins_slice(address)) else {
+            continue;
+        };
+        instructions.insert(address);
+
+        // Indirect jumps are only followed for the "set r0 1309, call r0" pattern.
+        let target = ins.jump_target().and_then(|t| match t {
+            IntReg::Value(v) => Some(v),
+            IntReg::Register(r) => loaded.filter(|(l, _)| *l == r).map(|(_, v)| v),
+        });
+        if let Some(target) = target {
+            if ins.name() == "call" {
+                labels.insert(target, Label::Function);
+            } else {
+                labels.entry(target).or_insert(Label::Location);
+            }
+            to_visit.push((target, None));
+        }
+        if ins.falls_through() {
+            to_visit.push((address + ins.offset(), ins.constant_load()));
+        }
+    }
+
+    Analysis {
+        instructions,
+        labels,
+    }
+}
+
+// Decompiled listing of the program, following the control flow from address 0.
+pub fn disassemble(program: &Program) -> String {
+    let mem = Memory::from_program(program);
+    listing(&mem, &analyze(&mem, &[(0, None)]))
+}
+
+fn listing(mem: &Memory, analysis: &Analysis) -> String {
+    // Each line of the listing: Its address, with the instruction there, or None for data.
+    let mut lines = Vec::new();
+    let mut address: u16 = 0;
+    while (address as usize) < mem.len() {
+        match decode(address, mem.ins_slice(address)) {
+            Ok(ins) if analysis.instructions.contains(&address) => {
+                lines.push((address, Some(ins)));
+                address += ins.offset();
+            }
+            _ => {
+                lines.push((address, None));
+                address += 1;
+            }
+        }
+    }
+    let starts: BTreeSet<u16> = lines.iter().map(|(a, _)| *a).collect();
+
+    let mut out = String::new();
+    for (address, ins) in lines {
+        if let Some(label) = analysis.labels.get(&address) {
+            out += &format!("{}:\n", label_name(address, *label));
         }
+        match ins {
+            Some(ins) => {
+                let mut line = ins.decompile();
+                // The target is the last argument, show its label instead. A target without a
+                // line of its own has no label, so it stays a number, with a note.
+                if let Some(IntReg::Value(target)) = ins.jump_target() {
+                    if starts.contains(&target) {
+                        let label = label_name(target, analysis.labels[&target]);
+                        line.truncate(line.rfind('\t').unwrap() + 1);
+                        line += &label;
+                    } else if (target as usize) < mem.len() {
+                        line += &format!("\t; target {} is not an instruction start", target);
+                    } else {
+                        line += &format!("\t; target {} is outside the program", target);
+                    }
+                }
+                out += &line;
+            }
+            None => out += &format!("{}\t.data\t{}", address, mem.read(address)),
+        }
+        out += "\n";
+    }
+    out
+}
+
+// Runs the program with the actions. Returns the memory as it was when the program first asked
+// for input, and the targets of the indirect calls and jumps that were executed.
+fn trace_indirect_jumps(
+    program: &Program,
+    actions: &[&str],
+) -> (Memory, Vec<(u16, Option<Label>)>) {
+    let mut vm = Vm::new(program);
+    let mut first_input_mem = None;
+    let mut targets = BTreeMap::new();
+
+    let mut actions = actions.iter();
+    loop {
+        if vm.wants_input() {
+            if first_input_mem.is_none() {
+                first_input_mem = Some(vm.storage().mem.clone());
+            }
+            match actions.next() {
+                Some(action) => vm.feed_line(action),
+                None => break,
+            }
+        }
+        if let Ok(ins) = vm.current_instruction() {
+            if let Some(IntReg::Register(r)) = ins.jump_target() {
+                let target = vm.storage().regs.to_array()[*r];
+                if ins.name() == "call" {
+                    targets.insert(target, Label::Function);
+                } else {
+                    targets.entry(target).or_insert(Label::Location);
+                }
+            }
+        }
+        if ExitReason::from_outcome(vm.step()).is_some() {
+            break;
+        }
+    }
+
+    let mem = first_input_mem.unwrap_or_else(|| vm.storage().mem.clone());
+    (
+        mem,
+        targets.into_iter().map(|(a, l)| (a, Some(l))).collect(),
+    )
+}
+
+// Decompiled listing of the program, first run with the actions to find the code reached indirectly.
+pub fn disassemble_traced(program: &Program, actions: &[&str]) -> String {
+    let (mem, mut entry_points) = trace_indirect_jumps(program, actions);
+    entry_points.push((0, None));
+    listing(&mem, &analyze(&mem, &entry_points))
+}
+
+pub fn decompile(program: &Program, actions: &[&str]) {
+    print!("{}", disassemble_traced(program, actions));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maze::maze_commands::COMMANDS;
+    use crate::vm::assembler::assemble;
+
+    #[test]
+    fn test_control_flow() {
+        let program = Program::from_words(vec![
+            17, 6, // 0: call 6
+            6, 9, // 2: jmp 9
+            19, 65, // 4: out 'A', never reached
+            7, 32768, 9,  // 6: jt r0 9
+            18, // 9: ret
+            0,  // 10: halt, never reached
+        ])
+        .unwrap();
+        assert_eq!(
+            disassemble(&program),
+            "0\tcall\tfn_6
+2\tjmp\tloc_9
+4\t.data\t19
+5\t.data\t65
+fn_6:
+6\tjt\tr0\tloc_9
+loc_9:
+9\tret
+10\t.data\t0
+"
+        );
+    }
+
+    #[test]
+    fn test_unlabelled_targets() {
+        let program = Program::from_words(vec![
+            7, 32768, 4, // 0: jt r0 4, in the middle of the next instruction
+            6, 100, // 3: jmp 100, outside the program
+        ])
+        .unwrap();
+        let listing = disassemble(&program);
+        assert_eq!(
+            listing,
+            "0\tjt\tr0\t4\t; target 4 is not an instruction start
+3\tjmp\t100\t; target 100 is outside the program
+"
+        );
+        assert_eq!(assemble(&listing).unwrap(), program.words());
+    }
+
+    #[test]
+    fn test_challenge() {
+        let listing = disassemble_traced(&Program::challenge().unwrap(), &COMMANDS);
+        assert!(listing.contains("5511\tcall\tfn_6049\n"));
+        assert!(listing.contains("fn_6049:\n6049\tjt\tr0\tloc_6057\n"));
+        assert!(listing.contains("loc_6057:\n6057\tjt\tr1\tloc_6070\n"));
     }
 }
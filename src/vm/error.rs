@@ -0,0 +1,61 @@
+use std::fmt;
+
+// What went wrong. These are the error cases the spec defines, plus decoding errors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmErrorKind {
+    InvalidOpcode(u16),
+    // Numbers 32776..65535 are invalid.
+    InvalidNumber(u16),
+    // A register was expected, but got a literal value or an invalid number.
+    InvalidRegister(u16),
+    // Memory was accessed or an instruction fetched outside the 15-bit address space.
+    InvalidAddress(u16),
+    EmptyStack,
+    DivisionByZero,
+}
+
+impl fmt::Display for VmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmErrorKind::InvalidOpcode(op) => write!(f, "Invalid opcode {}", op),
+            VmErrorKind::InvalidNumber(n) => write!(f, "Invalid number {}", n),
+            VmErrorKind::InvalidRegister(n) => write!(f, "Invalid register {}", n),
+            VmErrorKind::InvalidAddress(a) => write!(f, "Invalid address {}", a),
+            VmErrorKind::EmptyStack => write!(f, "Stack is empty"),
+            VmErrorKind::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+// A fault of the VM, with the address and instruction that caused it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub address: u16,
+    // The decompiled instruction, or the raw words if it could not be decoded.
+    pub instruction: String,
+    pub kind: VmErrorKind,
+}
+
+impl VmError {
+    pub fn new(address: u16, instruction: String, kind: VmErrorKind) -> Self {
+        Self {
+            address,
+            instruction,
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} ({})",
+            self.kind,
+            self.address,
+            self.instruction.replace('\t', " ")
+        )
+    }
+}
+
+impl std::error::Error for VmError {}
@@ -0,0 +1,131 @@
+//! Execution history, to be able to step backwards.
+//!
+//! Each executed instruction changes at most one register, one memory word, one stack element,
+//! and reads or writes at most one character. We only record these changes, which keeps
+//! the history small enough to be always on. Registers and memory set from the debugger are
+//! recorded the same way, so that going back undoes them in order.
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackDelta {
+    Unchanged,
+    Pushed,
+    // Value that was popped.
+    Popped(u16),
+}
+
+// What an instruction changed, with the previous values needed to undo it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub ir: u16,
+    // Register number and previous value.
+    pub reg_write: Option<(usize, u16)>,
+    // Address and previous value.
+    pub mem_write: Option<(u16, u16)>,
+    pub stack: StackDelta,
+    // Character consumed from the terminal input.
+    pub input_read: Option<char>,
+    pub output_written: bool,
+    // Made from the debugger rather than by executing an instruction, so not a step.
+    pub edit: bool,
+}
+
+// Bounded list of deltas, the oldest ones being dropped first.
+#[derive(Debug, Default)]
+pub struct History {
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            deltas: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.deltas.len() > capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn push(&mut self, delta: Delta) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+    }
+
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn delta(ir: u16) -> Delta {
+        Delta {
+            ir,
+            reg_write: None,
+            mem_write: None,
+            stack: StackDelta::Unchanged,
+            input_read: None,
+            output_written: false,
+            edit: false,
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut history = History::new(2);
+        history.push(delta(1));
+        history.push(delta(2));
+        history.push(delta(3));
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.pop().unwrap().ir, 3);
+        assert_eq!(history.pop().unwrap().ir, 2);
+        assert_eq!(history.pop(), None);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut history = History::new(0);
+        history.push(delta(1));
+        assert!(history.is_empty());
+
+        history.set_capacity(3);
+        history.push(delta(1));
+        history.push(delta(2));
+        history.set_capacity(1);
+        assert_eq!(history.pop().unwrap().ir, 2);
+    }
+}
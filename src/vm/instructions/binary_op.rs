@@ -1,6 +1,7 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{fault, Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
@@ -16,135 +17,155 @@ use crate::vm::terminal::Terminal;
 //   stores into <a> the bitwise and of <b> and <c>
 // or: 13 a b c
 //   stores into <a> the bitwise or of <b> and <c>
+#[derive(Clone, Copy)]
 pub struct BinaryOp {
-    name: &'static str,
-    binary_fn: fn(u16, u16) -> u16,
-
+    pub(crate) op: Operation,
     addr: u16,
-    a: RegNb,
-    b: IntReg,
-    c: IntReg,
+    pub(crate) a: RegNb,
+    pub(crate) b: IntReg,
+    pub(crate) c: IntReg,
+}
+
+// Operations return None if they cannot be computed (division by zero).
+fn add(x: u16, y: u16) -> Option<u16> {
+    Some(((x as u32 + y as u32) % 32768) as u16)
 }
 
-fn add(x: u16, y: u16) -> u16 {
-    ((x as u32 + y as u32) % 32768) as u16
+fn mult(x: u16, y: u16) -> Option<u16> {
+    Some(((x as u32 * y as u32) % 32768) as u16)
 }
 
-fn mult(x: u16, y: u16) -> u16 {
-    ((x as u32 * y as u32) % 32768) as u16
+fn modulo(x: u16, y: u16) -> Option<u16> {
+    x.checked_rem(y)
 }
 
-fn modulo(x: u16, y: u16) -> u16 {
-    x % y
+fn and(x: u16, y: u16) -> Option<u16> {
+    Some(x & y)
 }
 
-fn and(x: u16, y: u16) -> u16 {
-    x & y
+fn or(x: u16, y: u16) -> Option<u16> {
+    Some(x | y)
 }
 
-fn or(x: u16, y: u16) -> u16 {
-    x | y
+#[derive(Clone, Copy)]
+pub(crate) enum Operation {
+    Add,
+    Mult,
+    Mod,
+    And,
+    Or,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Add => "add",
+            Operation::Mult => "mult",
+            Operation::Mod => "mod",
+            Operation::And => "and",
+            Operation::Or => "or",
+        }
+    }
+
+    fn sign(self) -> &'static str {
+        match self {
+            Operation::Add => "+",
+            Operation::Mult => "*",
+            Operation::Mod => "%",
+            Operation::And => "&",
+            Operation::Or => "|",
+        }
+    }
+
+    fn apply(self, x: u16, y: u16) -> Option<u16> {
+        match self {
+            Operation::Add => add(x, y),
+            Operation::Mult => mult(x, y),
+            Operation::Mod => modulo(x, y),
+            Operation::And => and(x, y),
+            Operation::Or => or(x, y),
+        }
+    }
 }
 
 impl BinaryOp {
     const ARGS_COUNT: u16 = 3;
 
-    fn new(
-        name: &'static str,
-        binary_fn: fn(u16, u16) -> u16,
-        addr: u16,
-        a: RegNb,
-        b: IntReg,
-        c: IntReg,
-    ) -> Self {
-        Self {
-            name,
-            binary_fn,
-            addr,
-            a,
-            b,
-            c,
-        }
+    fn new(op: Operation, addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
+        Self { op, addr, a, b, c }
     }
 
     fn add(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("add", add, addr, a, b, c)
+        Self::new(Operation::Add, addr, a, b, c)
     }
 
     fn mult(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("mult", mult, addr, a, b, c)
+        Self::new(Operation::Mult, addr, a, b, c)
     }
 
     fn modulo(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("mod", modulo, addr, a, b, c)
+        Self::new(Operation::Mod, addr, a, b, c)
     }
 
     fn and(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("and", and, addr, a, b, c)
+        Self::new(Operation::And, addr, a, b, c)
     }
 
     fn or(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("or", or, addr, a, b, c)
+        Self::new(Operation::Or, addr, a, b, c)
     }
 
-    pub fn inst_add(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
+    pub fn inst_add(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
         // For "add", spec says "assign into <a>", while for the other operations
         // it says "store into <a>".
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::add(addr, a, b, c))
-    }
-
-    pub fn inst_mult(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::mult(addr, a, b, c))
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::add(addr, a, b, c).into())
     }
 
-    pub fn inst_mod(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::modulo(addr, a, b, c))
+    pub fn inst_mult(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::mult(addr, a, b, c).into())
     }
 
-    pub fn inst_and(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::and(addr, a, b, c))
+    pub fn inst_mod(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::modulo(addr, a, b, c).into())
     }
 
-    pub fn inst_or(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::or(addr, a, b, c))
+    pub fn inst_and(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::and(addr, a, b, c).into())
     }
 
-    fn sign(&self) -> &'static str {
-        match self.name {
-            "add" => "+",
-            "mult" => "*",
-            "mod" => "%",
-            "and" => "&",
-            "or" => "|",
-            _ => panic!("Invalid name"),
-        }
+    pub fn inst_or(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::or(addr, a, b, c).into())
     }
 }
 
 impl Instruction for BinaryOp {
     fn name(&self) -> &'static str {
-        self.name
+        self.op.name()
     }
 
     fn offset(&self) -> u16 {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!(
             "{}\t{}\t{}\t{}\t{}",
@@ -156,12 +177,17 @@ impl Instruction for BinaryOp {
         )
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        st.regs.set(
-            self.a,
-            (self.binary_fn)(st.regs.get_ir(self.b), st.regs.get_ir(self.c)),
-        );
-        *ir += 1 + Self::ARGS_COUNT;
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        if let Some(val) = self
+            .op
+            .apply(st.regs.get_ir(self.b), st.regs.get_ir(self.c))
+        {
+            st.regs.set(self.a, val);
+            *ir += 1 + Self::ARGS_COUNT;
+            StepOutcome::Continue
+        } else {
+            fault(self.addr, self, VmErrorKind::DivisionByZero)
+        }
     }
 }
 
@@ -170,10 +196,10 @@ impl fmt::Display for BinaryOp {
         write!(
             f,
             "{}: {} = {} {} {}",
-            self.name,
+            self.name(),
             self.a,
             self.b,
-            self.sign(),
+            self.op.sign(),
             self.c,
         )
     }
@@ -189,10 +215,10 @@ mod test {
         let a = 16384;
         let b = 16384;
         assert_eq!(a + b, 32768);
-        assert_eq!(add(a, b), 0);
+        assert_eq!(add(a, b), Some(0));
         // Adding 32767 is same as -1
-        assert_eq!(add(10, 32767), 9);
-        assert_eq!(add(45, 32767), 44);
+        assert_eq!(add(10, 32767), Some(9));
+        assert_eq!(add(45, 32767), Some(44));
     }
 
     #[test]
@@ -244,6 +270,11 @@ mod test {
         ins.exec(&mut ir, &mut storage, &mut terminal);
         assert_eq!(storage.regs.get(RegNb::new(3)), 1);
         assert_eq!(ir, 104);
+
+        storage.regs.set(RegNb::new(4), 0);
+        let outcome = ins.exec(&mut ir, &mut storage, &mut terminal);
+        assert!(matches!(outcome, StepOutcome::Fault(e) if e.kind == VmErrorKind::DivisionByZero));
+        assert_eq!(ir, 104);
     }
 
     #[test]
@@ -1,15 +1,17 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // call: 17 a
 //   write the address of the next instruction to the stack and jump to <a>
+#[derive(Clone, Copy)]
 pub struct Call {
     addr: u16,
-    a: IntReg,
+    pub(crate) a: IntReg,
 }
 
 impl Call {
@@ -19,9 +21,9 @@ impl Call {
         Self { addr, a }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        Box::new(Self::new(addr, a))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        Ok(Self::new(addr, a).into())
     }
 }
 
@@ -38,9 +40,14 @@ impl Instruction for Call {
         format!("{}\t{}\t{}", self.addr, self.name(), self.a)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
+    fn jump_target(&self) -> Option<IntReg> {
+        Some(self.a)
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
         st.stack.push(*ir + 1 + Self::ARGS_COUNT);
         *ir = st.regs.get_ir(self.a);
+        StepOutcome::Continue
     }
 }
 
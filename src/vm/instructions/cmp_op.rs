@@ -1,6 +1,7 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
@@ -10,13 +11,13 @@ use crate::vm::terminal::Terminal;
 //   set <a> to 1 if <b> is equal to <c>; set it to 0 otherwise
 // gt: 5 a b c
 //   set <a> to 1 if <b> is greater than <c>; set it to 0 otherwise
+#[derive(Clone, Copy)]
 pub struct CmpOp {
-    name: &'static str,
-    cmp_fn: fn(u16, u16) -> bool,
+    pub(crate) cmp: Comparison,
     addr: u16,
-    a: RegNb,
-    b: IntReg,
-    c: IntReg,
+    pub(crate) a: RegNb,
+    pub(crate) b: IntReg,
+    pub(crate) c: IntReg,
 }
 
 fn eq(x: u16, y: u16) -> bool {
@@ -27,67 +28,78 @@ fn gt(x: u16, y: u16) -> bool {
     x > y
 }
 
+#[derive(Clone, Copy)]
+pub(crate) enum Comparison {
+    Eq,
+    Gt,
+}
+
+impl Comparison {
+    fn name(self) -> &'static str {
+        match self {
+            Comparison::Eq => "eq",
+            Comparison::Gt => "gt",
+        }
+    }
+
+    fn sign(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Gt => ">",
+        }
+    }
+
+    fn apply(self, x: u16, y: u16) -> bool {
+        match self {
+            Comparison::Eq => eq(x, y),
+            Comparison::Gt => gt(x, y),
+        }
+    }
+}
+
 impl CmpOp {
     const ARGS_COUNT: u16 = 3;
 
-    fn new(
-        name: &'static str,
-        cmp_fn: fn(u16, u16) -> bool,
-        addr: u16,
-        a: RegNb,
-        b: IntReg,
-        c: IntReg,
-    ) -> Self {
-        Self {
-            name,
-            cmp_fn,
-            addr,
-            a,
-            b,
-            c,
-        }
+    fn new(cmp: Comparison, addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
+        Self { cmp, addr, a, b, c }
     }
 
     fn eq(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("eq", eq, addr, a, b, c)
+        Self::new(Comparison::Eq, addr, a, b, c)
     }
 
     fn gt(addr: u16, a: RegNb, b: IntReg, c: IntReg) -> Self {
-        Self::new("gt", gt, addr, a, b, c)
+        Self::new(Comparison::Gt, addr, a, b, c)
     }
 
-    pub fn inst_eq(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::eq(addr, a, b, c))
+    pub fn inst_eq(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::eq(addr, a, b, c).into())
     }
 
-    pub fn inst_gt(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        let c = IntReg::new(mem[3]);
-        Box::new(Self::gt(addr, a, b, c))
-    }
-
-    fn sign(&self) -> &'static str {
-        match self.name {
-            "eq" => "==",
-            "gt" => ">",
-            _ => panic!("Invalid name"),
-        }
+    pub fn inst_gt(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        let c = IntReg::new(mem[3])?;
+        Ok(Self::gt(addr, a, b, c).into())
     }
 }
 
 impl Instruction for CmpOp {
     fn name(&self) -> &'static str {
-        self.name
+        self.cmp.name()
     }
 
     fn offset(&self) -> u16 {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!(
             "{}\t{}\t{}\t{}\t{}",
@@ -99,16 +111,20 @@ impl Instruction for CmpOp {
         )
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
         st.regs.set(
             self.a,
-            if (self.cmp_fn)(st.regs.get_ir(self.b), st.regs.get_ir(self.c)) {
+            if self
+                .cmp
+                .apply(st.regs.get_ir(self.b), st.regs.get_ir(self.c))
+            {
                 1
             } else {
                 0
             },
         );
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
@@ -120,7 +136,7 @@ impl fmt::Display for CmpOp {
             self.name(),
             self.a,
             self.b,
-            self.sign(),
+            self.cmp.sign(),
             self.c
         )
     }
@@ -1,11 +1,13 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // halt: 0
 // stop execution and terminate the program
+#[derive(Clone, Copy)]
 pub struct Halt {
     addr: u16,
 }
@@ -17,8 +19,8 @@ impl Halt {
         Self { addr }
     }
 
-    pub fn inst(addr: u16, _mem: &[u16]) -> Box<dyn Instruction> {
-        Box::new(Self::new(addr))
+    pub fn inst(addr: u16, _mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        Ok(Self::new(addr).into())
     }
 }
 
@@ -35,9 +37,12 @@ impl Instruction for Halt {
         format!("{}\t{}", self.addr, self.name())
     }
 
-    fn exec(&self, _ir: &mut u16, _st: &mut Storage, _term: &mut Terminal) {
-        println!("Halting");
-        std::process::exit(0);
+    fn falls_through(&self) -> bool {
+        false
+    }
+
+    fn exec(&self, _ir: &mut u16, _st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        StepOutcome::Halted
     }
 }
 
@@ -46,3 +51,17 @@ impl fmt::Display for Halt {
         write!(f, "Halt")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exec() {
+        let ins = Halt::new(1);
+        let mut ir = 100;
+        let outcome = ins.exec(&mut ir, &mut Storage::new(), &mut Terminal::new(false));
+        assert_eq!(outcome, StepOutcome::Halted);
+        assert_eq!(ir, 100);
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
@@ -11,9 +12,10 @@ use crate::vm::terminal::Terminal;
 // a newline is encountered; this means that you can safely read whole
 // lines from the keyboard instead of having to figure out how to read
 // individual characters
+#[derive(Clone, Copy)]
 pub struct In {
     addr: u16,
-    a: RegNb,
+    pub(crate) a: RegNb,
 }
 
 impl In {
@@ -23,9 +25,9 @@ impl In {
         Self { addr, a }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        Box::new(Self::new(addr, a))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        Ok(Self::new(addr, a).into())
     }
 }
 
@@ -38,17 +40,22 @@ impl Instruction for In {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!("{}\t{}\t{}", self.addr, self.name(), self.a)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) {
+    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) -> StepOutcome {
         if let Some(c) = term.read() {
             st.regs.set(self.a, c as u16);
             *ir += 1 + Self::ARGS_COUNT;
+            StepOutcome::Continue
         } else {
-            assert!(term.is_interactive_mode());
             // By not modifying ir in case read returned None, we ensure that next exec attempt will try on this instruction again.
+            StepOutcome::WaitingForInput
         }
     }
 }
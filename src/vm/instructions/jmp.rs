@@ -1,15 +1,17 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // jmp: 6 a
 // jump to <a>
+#[derive(Clone, Copy)]
 pub struct Jmp {
     addr: u16,
-    a: IntReg,
+    pub(crate) a: IntReg,
 }
 
 impl Jmp {
@@ -19,9 +21,9 @@ impl Jmp {
         Self { addr, a }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        Box::new(Self::new(addr, a))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        Ok(Self::new(addr, a).into())
     }
 }
 
@@ -38,8 +40,17 @@ impl Instruction for Jmp {
         format!("{}\t{}\t{}", self.addr, self.name(), self.a)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
+    fn jump_target(&self) -> Option<IntReg> {
+        Some(self.a)
+    }
+
+    fn falls_through(&self) -> bool {
+        false
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
         *ir = st.regs.get_ir(self.a);
+        StepOutcome::Continue
     }
 }
 
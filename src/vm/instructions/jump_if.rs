@@ -1,6 +1,7 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
@@ -9,21 +10,21 @@ use crate::vm::terminal::Terminal;
 //   if <a> is nonzero, jump to <b>
 // jf: 8 a b
 //   if <a> is zero, jump to <b>
+#[derive(Clone, Copy)]
 pub struct JumpIf {
-    name: &'static str,
-    cond_fn: fn(u16) -> bool,
+    // Jump if <a> is nonzero (jt), or if it is zero (jf).
+    pub(crate) if_true: bool,
     addr: u16,
-    a: IntReg,
-    b: IntReg,
+    pub(crate) a: IntReg,
+    pub(crate) b: IntReg,
 }
 
 impl JumpIf {
     const ARGS_COUNT: u16 = 2;
 
-    fn new(name: &'static str, cond_fn: fn(u16) -> bool, addr: u16, a: IntReg, b: IntReg) -> Self {
+    fn new(if_true: bool, addr: u16, a: IntReg, b: IntReg) -> Self {
         Self {
-            name,
-            cond_fn,
+            if_true,
             addr,
             a,
             b,
@@ -31,29 +32,33 @@ impl JumpIf {
     }
 
     fn jt(addr: u16, a: IntReg, b: IntReg) -> Self {
-        Self::new("jt", |v| v != 0, addr, a, b)
+        Self::new(true, addr, a, b)
     }
 
     fn jf(addr: u16, a: IntReg, b: IntReg) -> Self {
-        Self::new("jf", |v| v == 0, addr, a, b)
+        Self::new(false, addr, a, b)
     }
 
-    pub fn inst_jt(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        let b = IntReg::new(mem[2]);
-        Box::new(Self::jt(addr, a, b))
+    pub fn inst_jt(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        Ok(Self::jt(addr, a, b).into())
     }
 
-    pub fn inst_jf(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        let b = IntReg::new(mem[2]);
-        Box::new(Self::jf(addr, a, b))
+    pub fn inst_jf(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        Ok(Self::jf(addr, a, b).into())
     }
 }
 
 impl Instruction for JumpIf {
     fn name(&self) -> &'static str {
-        self.name
+        if self.if_true {
+            "jt"
+        } else {
+            "jf"
+        }
     }
 
     fn offset(&self) -> u16 {
@@ -64,12 +69,17 @@ impl Instruction for JumpIf {
         format!("{}\t{}\t{}\t{}", self.addr, self.name(), self.a, self.b)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        if (self.cond_fn)(st.regs.get_ir(self.a)) {
+    fn jump_target(&self) -> Option<IntReg> {
+        Some(self.b)
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        if (st.regs.get_ir(self.a) != 0) == self.if_true {
             *ir = st.regs.get_ir(self.b);
         } else {
             *ir += 1 + Self::ARGS_COUNT;
         }
+        StepOutcome::Continue
     }
 }
 
@@ -78,10 +88,10 @@ impl fmt::Display for JumpIf {
         write!(
             f,
             "{}: Jump to {} if {} {}",
-            self.name,
+            self.name(),
             self.b,
             self.a,
-            if self.name == "jt" { "!= 0" } else { "== 0" }
+            if self.if_true { "!= 0" } else { "== 0" }
         )
     }
 }
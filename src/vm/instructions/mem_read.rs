@@ -1,17 +1,20 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{fault, Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
+use crate::vm::program::is_address;
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // rmem: 15 a b
 //   read memory at address <b> and write it to <a>
+#[derive(Clone, Copy)]
 pub struct RMem {
     addr: u16,
-    a: RegNb,
-    b: IntReg,
+    pub(crate) a: RegNb,
+    pub(crate) b: IntReg,
 }
 
 impl RMem {
@@ -21,10 +24,10 @@ impl RMem {
         Self { addr, a, b }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        Box::new(Self::new(addr, a, b))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        Ok(Self::new(addr, a, b).into())
     }
 }
 
@@ -37,13 +40,22 @@ impl Instruction for RMem {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!("{}\t{}\t{}\t{}", self.addr, self.name(), self.a, self.b)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        st.regs.set(self.a, st.mem.read(st.regs.get_ir(self.b)));
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        let address = st.regs.get_ir(self.b);
+        if !is_address(address) {
+            return fault(self.addr, self, VmErrorKind::InvalidAddress(address));
+        }
+        st.regs.set(self.a, st.mem.read(address));
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
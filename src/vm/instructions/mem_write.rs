@@ -1,16 +1,19 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{fault, Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
+use crate::vm::program::is_address;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // wmem: 16 a b
 //   write the value from <b> into memory at address <a>
+#[derive(Clone, Copy)]
 pub struct WMem {
     addr: u16,
-    a: IntReg,
-    b: IntReg,
+    pub(crate) a: IntReg,
+    pub(crate) b: IntReg,
 }
 
 impl WMem {
@@ -20,10 +23,10 @@ impl WMem {
         Self { addr, a, b }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        let b = IntReg::new(mem[2]);
-        Box::new(Self::new(addr, a, b))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        Ok(Self::new(addr, a, b).into())
     }
 }
 
@@ -40,9 +43,14 @@ impl Instruction for WMem {
         format!("{}\t{}\t{}\t{}", self.addr, self.name(), self.a, self.b)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        st.mem.write(st.regs.get_ir(self.a), st.regs.get_ir(self.b));
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        let address = st.regs.get_ir(self.a);
+        if !is_address(address) {
+            return fault(self.addr, self, VmErrorKind::InvalidAddress(address));
+        }
+        st.mem.write(address, st.regs.get_ir(self.b));
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
@@ -15,20 +15,137 @@ mod stack_pop;
 mod stack_push;
 mod unary_op;
 
-use std::fmt::Display;
+use std::fmt::{self, Display};
 
+pub(crate) use binary_op::Operation;
+pub(crate) use cmp_op::Comparison;
+
+use crate::vm::error::{VmError, VmErrorKind};
+use crate::vm::intreg::IntReg;
+use crate::vm::program::is_address;
+use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
+// What happened when executing an instruction.
+#[derive(Debug, PartialEq)]
+pub enum StepOutcome {
+    Continue,
+    Halted,
+    // No input available. The instruction pointer isn't moved, so the same instruction is retried.
+    WaitingForInput,
+    Fault(VmError),
+}
+
 pub trait Instruction: Display {
     fn name(&self) -> &'static str;
     fn offset(&self) -> u16;
     fn decompile(&self) -> String;
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal);
+    // Operand with the address this instruction may jump to.
+    fn jump_target(&self) -> Option<IntReg> {
+        None
+    }
+
+    // Register and value, for an instruction that loads a literal into a register.
+    fn constant_load(&self) -> Option<(RegNb, u16)> {
+        None
+    }
+
+    // Register this instruction writes to, even when the value stays the same.
+    fn written_register(&self) -> Option<RegNb> {
+        None
+    }
+
+    // False if the next instruction is never executed after this one.
+    fn falls_through(&self) -> bool {
+        true
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) -> StepOutcome;
+}
+
+// Defines the Ins enum with one variant per instruction type, implementing Instruction by
+// forwarding to the variant.
+macro_rules! instruction_enum {
+    ($($variant:ident($ty:ty)),* $(,)?) => {
+        // A decoded instruction. A plain value rather than a boxed trait object, so that decoding
+        // doesn't allocate and decoded instructions can be cached.
+        #[derive(Clone, Copy)]
+        pub enum Ins {
+            $($variant($ty)),*
+        }
+
+        $(
+            impl From<$ty> for Ins {
+                fn from(ins: $ty) -> Self {
+                    Ins::$variant(ins)
+                }
+            }
+        )*
+
+        impl Instruction for Ins {
+            fn name(&self) -> &'static str {
+                match self { $(Ins::$variant(ins) => ins.name()),* }
+            }
+
+            fn offset(&self) -> u16 {
+                match self { $(Ins::$variant(ins) => ins.offset()),* }
+            }
+
+            fn decompile(&self) -> String {
+                match self { $(Ins::$variant(ins) => ins.decompile()),* }
+            }
+
+            fn jump_target(&self) -> Option<IntReg> {
+                match self { $(Ins::$variant(ins) => ins.jump_target()),* }
+            }
+
+            fn constant_load(&self) -> Option<(RegNb, u16)> {
+                match self { $(Ins::$variant(ins) => ins.constant_load()),* }
+            }
+
+            fn written_register(&self) -> Option<RegNb> {
+                match self { $(Ins::$variant(ins) => ins.written_register()),* }
+            }
+
+            fn falls_through(&self) -> bool {
+                match self { $(Ins::$variant(ins) => ins.falls_through()),* }
+            }
+
+            fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) -> StepOutcome {
+                match self { $(Ins::$variant(ins) => ins.exec(ir, st, term)),* }
+            }
+        }
+
+        impl fmt::Display for Ins {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self { $(Ins::$variant(ins) => ins.fmt(f)),* }
+            }
+        }
+    };
 }
 
-type InstanceFn = fn(u16, &[u16]) -> Box<dyn Instruction>;
+instruction_enum! {
+    Halt(halt::Halt),
+    Set(set::Set),
+    Push(stack_push::Push),
+    Pop(stack_pop::Pop),
+    CmpOp(cmp_op::CmpOp),
+    Jmp(jmp::Jmp),
+    JumpIf(jump_if::JumpIf),
+    BinaryOp(binary_op::BinaryOp),
+    Not(unary_op::Not),
+    RMem(mem_read::RMem),
+    WMem(mem_write::WMem),
+    Call(call::Call),
+    Ret(ret::Ret),
+    Out(out::Out),
+    In(input::In),
+    Noop(noop::Noop),
+}
+
+type InstanceFn = fn(u16, &[u16]) -> Result<Ins, VmErrorKind>;
 
 pub const BUILDERS: [InstanceFn; 22] = [
     halt::Halt::inst,               // 0
@@ -59,8 +176,53 @@ pub fn is_opcode(val: u16) -> bool {
     (0..=21).contains(&val)
 }
 
-pub fn get_instruction(storage: &Storage, address: u16) -> Box<dyn Instruction> {
-    let opcode = storage.mem.read(address);
-    assert!(is_opcode(opcode));
-    BUILDERS[opcode as usize](address, storage.mem.ins_slice(address))
+// Decodes the instruction at this address.
+pub fn decode(address: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+    let opcode = mem[0];
+    if !is_opcode(opcode) {
+        return Err(VmErrorKind::InvalidOpcode(opcode));
+    }
+    BUILDERS[opcode as usize](address, mem)
+}
+
+pub fn get_instruction(storage: &Storage, address: u16) -> Result<Ins, VmError> {
+    if !is_address(address) {
+        return Err(VmError::new(
+            address,
+            String::new(),
+            VmErrorKind::InvalidAddress(address),
+        ));
+    }
+    let mem = storage.mem.ins_slice(address);
+    decode(address, mem).map_err(|kind| {
+        let words = mem.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        VmError::new(address, words.join(" "), kind)
+    })
+}
+
+// Same as get_instruction, but reuses the instruction decoded at this address before, if its
+// words weren't written since.
+pub fn get_cached_instruction(storage: &mut Storage, address: u16) -> Result<Ins, VmError> {
+    if let Some(ins) = storage.mem.cached_instruction(address) {
+        return Ok(ins);
+    }
+    let ins = get_instruction(storage, address)?;
+    storage.mem.cache_instruction(address, ins);
+    Ok(ins)
+}
+
+// Fault raised by an instruction while executing.
+fn fault(addr: u16, ins: &dyn Instruction, kind: VmErrorKind) -> StepOutcome {
+    StepOutcome::Fault(VmError::new(addr, ins.decompile(), kind))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compact() {
+        // Small enough for a cache of the whole address space.
+        assert!(std::mem::size_of::<Ins>() <= 16);
+    }
 }
@@ -1,11 +1,13 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // noop: 21
 // no operation
+#[derive(Clone, Copy)]
 pub struct Noop {
     addr: u16,
 }
@@ -17,8 +19,8 @@ impl Noop {
         Self { addr }
     }
 
-    pub fn inst(addr: u16, _mem: &[u16]) -> Box<dyn Instruction> {
-        Box::new(Self::new(addr))
+    pub fn inst(addr: u16, _mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        Ok(Self::new(addr).into())
     }
 }
 
@@ -35,8 +37,9 @@ impl Instruction for Noop {
         format!("{}\t{}", self.addr, self.name())
     }
 
-    fn exec(&self, ir: &mut u16, _st: &mut Storage, _term: &mut Terminal) {
+    fn exec(&self, ir: &mut u16, _st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
@@ -1,15 +1,17 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // out: 19 a
 // write the character represented by ascii code <a> to the terminal
+#[derive(Clone, Copy)]
 pub struct Out {
     addr: u16,
-    a: IntReg,
+    pub(crate) a: IntReg,
 }
 
 impl Out {
@@ -19,9 +21,9 @@ impl Out {
         Self { addr, a }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = IntReg::new(mem[1]);
-        Box::new(Self::new(addr, a))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = IntReg::new(mem[1])?;
+        Ok(Self::new(addr, a).into())
     }
 }
 
@@ -38,9 +40,10 @@ impl Instruction for Out {
         format!("{}\t{}\t{}", self.addr, self.name(), self.a)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) {
+    fn exec(&self, ir: &mut u16, st: &mut Storage, term: &mut Terminal) -> StepOutcome {
         term.write(st.regs.get_ir(self.a) as u8 as char);
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
@@ -1,11 +1,13 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // ret: 18
 //   remove the top element from the stack and jump to it; empty stack = halt
+#[derive(Clone, Copy)]
 pub struct Ret {
     addr: u16,
 }
@@ -17,8 +19,8 @@ impl Ret {
         Self { addr }
     }
 
-    pub fn inst(addr: u16, _mem: &[u16]) -> Box<dyn Instruction> {
-        Box::new(Self::new(addr))
+    pub fn inst(addr: u16, _mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        Ok(Self::new(addr).into())
     }
 }
 
@@ -35,9 +37,18 @@ impl Instruction for Ret {
         format!("{}\t{}", self.addr, self.name())
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        let address = st.stack.pop().expect("Stack is empty");
-        *ir = address;
+    fn falls_through(&self) -> bool {
+        false
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        // As per the spec, returning with an empty stack halts the program.
+        if let Some(address) = st.stack.pop() {
+            *ir = address;
+            StepOutcome::Continue
+        } else {
+            StepOutcome::Halted
+        }
     }
 }
 
@@ -61,4 +72,12 @@ mod test {
         ins.exec(&mut ir, &mut storage, &mut Terminal::new(false));
         assert_eq!(ir, 478);
     }
+
+    #[test]
+    fn test_exec_empty_stack() {
+        let ins = Ret::new(1);
+        let mut ir = 100;
+        let outcome = ins.exec(&mut ir, &mut Storage::new(), &mut Terminal::new(false));
+        assert_eq!(outcome, StepOutcome::Halted);
+    }
 }
@@ -1,6 +1,7 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{Ins, Instruction, StepOutcome};
 use crate::vm::intreg::IntReg;
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
@@ -8,10 +9,11 @@ use crate::vm::terminal::Terminal;
 
 // set: 1 a b
 //   set register <a> to the value of <b>
+#[derive(Clone, Copy)]
 pub struct Set {
     addr: u16,
-    a: RegNb,
-    b: IntReg,
+    pub(crate) a: RegNb,
+    pub(crate) b: IntReg,
 }
 
 impl Set {
@@ -21,10 +23,10 @@ impl Set {
         Self { addr, a, b }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        let b = IntReg::new(mem[2]);
-        Box::new(Self::new(addr, a, b))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        let b = IntReg::new(mem[2])?;
+        Ok(Self::new(addr, a, b).into())
     }
 }
 
@@ -37,13 +39,22 @@ impl Instruction for Set {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!("{}\t{}\t{}\t{}", self.addr, self.name(), self.a, self.b)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
+    fn constant_load(&self) -> Option<(RegNb, u16)> {
+        self.b.literal().map(|v| (self.a, v))
+    }
+
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
         st.regs.set(self.a, st.regs.get_ir(self.b));
         *ir += 1 + Self::ARGS_COUNT;
+        StepOutcome::Continue
     }
 }
 
@@ -1,15 +1,17 @@
 use std::fmt;
 
-use crate::vm::instructions::Instruction;
+use crate::vm::error::VmErrorKind;
+use crate::vm::instructions::{fault, Ins, Instruction, StepOutcome};
 use crate::vm::register::RegNb;
 use crate::vm::storage::Storage;
 use crate::vm::terminal::Terminal;
 
 // pop: 3 a
 //   remove the top element from the stack and write it into <a>; empty stack = error
+#[derive(Clone, Copy)]
 pub struct Pop {
     addr: u16,
-    a: RegNb,
+    pub(crate) a: RegNb,
 }
 
 impl Pop {
@@ -19,9 +21,9 @@ impl Pop {
         Self { addr, a }
     }
 
-    pub fn inst(addr: u16, mem: &[u16]) -> Box<dyn Instruction> {
-        let a = RegNb::from(mem[1]);
-        Box::new(Self::new(addr, a))
+    pub fn inst(addr: u16, mem: &[u16]) -> Result<Ins, VmErrorKind> {
+        let a = RegNb::try_from(mem[1])?;
+        Ok(Self::new(addr, a).into())
     }
 }
 
@@ -34,13 +36,22 @@ impl Instruction for Pop {
         1 + Self::ARGS_COUNT
     }
 
+    fn written_register(&self) -> Option<RegNb> {
+        Some(self.a)
+    }
+
     fn decompile(&self) -> String {
         format!("{}\t{}\t{}", self.addr, self.name(), self.a)
     }
 
-    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) {
-        st.regs.set(self.a, st.stack.pop().expect("Stack is empty"));
-        *ir += 1 + Self::ARGS_COUNT;
+    fn exec(&self, ir: &mut u16, st: &mut Storage, _term: &mut Terminal) -> StepOutcome {
+        if let Some(val) = st.stack.pop() {
+            st.regs.set(self.a, val);
+            *ir += 1 + Self::ARGS_COUNT;
+            StepOutcome::Continue
+        } else {
+            fault(self.addr, self, VmErrorKind::EmptyStack)
+        }
     }
 }
 
@@ -65,5 +76,11 @@ mod test {
         ins2.exec(&mut ir, &mut storage, &mut terminal);
         assert_eq!(storage.regs.get(RegNb::new(3)), 444);
         assert_eq!(ir, 102);
+
+        let outcome = ins2.exec(&mut ir, &mut storage, &mut terminal);
+        assert!(
+            matches!(outcome, StepOutcome::Fault(e) if e.kind == VmErrorKind::EmptyStack && e.address == 1)
+        );
+        assert_eq!(ir, 102);
     }
 }
@@ -1,5 +1,6 @@
 use std::fmt;
 
+use crate::vm::error::VmErrorKind;
 use crate::vm::register::RegNb;
 
 // The numbers in the binary format can mean two things: A literal value or a register number.
@@ -10,11 +11,19 @@ pub enum IntReg {
 }
 
 impl IntReg {
-    pub fn new(n: u16) -> Self {
+    pub fn new(n: u16) -> Result<Self, VmErrorKind> {
         match n {
-            0..=32767 => IntReg::Value(n),
-            32768..=32775 => IntReg::Register(RegNb::from(n)),
-            _ => panic!("Invalid number"),
+            0..=32767 => Ok(IntReg::Value(n)),
+            32768..=32775 => Ok(IntReg::Register(RegNb::try_from(n)?)),
+            _ => Err(VmErrorKind::InvalidNumber(n)),
+        }
+    }
+
+    // The literal value, or None for a register.
+    pub fn literal(&self) -> Option<u16> {
+        match self {
+            IntReg::Value(v) => Some(*v),
+            IntReg::Register(_) => None,
         }
     }
 }
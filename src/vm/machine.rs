@@ -0,0 +1,481 @@
+//! The virtual machine: Instruction pointer, storage and terminal in a single place.
+
+use std::fmt;
+
+use crate::vm::error::VmError;
+use crate::vm::history::{Delta, History, StackDelta};
+use crate::vm::instructions::{
+    get_cached_instruction, get_instruction, Ins, Instruction, StepOutcome,
+};
+use crate::vm::program::{is_address, Program};
+use crate::vm::register::{RegNb, Registers};
+use crate::vm::snapshot::Snapshot;
+use crate::vm::storage::Storage;
+use crate::vm::terminal::Terminal;
+
+// Opcode of the `in` instruction.
+const IN_OPCODE: u16 = 20;
+
+// Why the program stopped running.
+#[derive(Debug, PartialEq)]
+pub enum ExitReason {
+    Halted,
+    // The program wants input, but there is nothing more to give it.
+    InputExhausted,
+    Fault(VmError),
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Halted => write!(f, "Halting"),
+            ExitReason::InputExhausted => write!(f, "No more input"),
+            ExitReason::Fault(e) => write!(f, "Fault: {}", e),
+        }
+    }
+}
+
+impl ExitReason {
+    // Converts the outcome of an instruction into the reason to stop, if the program cannot continue.
+    pub fn from_outcome(outcome: StepOutcome) -> Option<Self> {
+        match outcome {
+            StepOutcome::Continue => None,
+            StepOutcome::Halted => Some(ExitReason::Halted),
+            StepOutcome::WaitingForInput => Some(ExitReason::InputExhausted),
+            StepOutcome::Fault(e) => Some(ExitReason::Fault(e)),
+        }
+    }
+}
+
+pub struct Vm {
+    ir: u16,
+    storage: Storage,
+    terminal: Terminal,
+    // Number of instructions executed so far.
+    steps: u64,
+    history: History,
+    // Memory write done by the last executed instruction: Address and previous value.
+    last_write: Option<(u16, u16)>,
+}
+
+// State before executing an instruction, to compute what the instruction changed.
+struct PreState {
+    ir: u16,
+    regs: [u16; 8],
+    stack_top: Option<u16>,
+    stack_len: usize,
+    next_input: Option<char>,
+    input_len: usize,
+    output_len: usize,
+}
+
+impl Vm {
+    // A VM that isn't connected to the real terminal: Output is only collected and input must be fed.
+    pub fn new(program: &Program) -> Self {
+        Self::with_terminal(program, Terminal::new(false))
+    }
+
+    pub fn with_terminal(program: &Program, terminal: Terminal) -> Self {
+        Self {
+            ir: 0,
+            storage: Storage::from_program(program),
+            terminal,
+            steps: 0,
+            history: History::default(),
+            last_write: None,
+        }
+    }
+
+    pub fn ir(&self) -> u16 {
+        self.ir
+    }
+
+    pub fn set_ir(&mut self, ir: u16) {
+        self.ir = ir;
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut Storage {
+        &mut self.storage
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+
+    // The instruction that will be executed next.
+    pub fn current_instruction(&self) -> Result<Ins, VmError> {
+        get_instruction(&self.storage, self.ir)
+    }
+
+    // True if the next instruction reads input and none is available.
+    pub fn wants_input(&self) -> bool {
+        is_address(self.ir)
+            && self.storage.mem.read(self.ir) == IN_OPCODE
+            && self.terminal.is_input_empty()
+    }
+
+    // Executes one instruction.
+    pub fn step(&mut self) -> StepOutcome {
+        let pre_state = self.history.is_enabled().then(|| self.pre_state());
+        self.storage.mem.take_last_write();
+        self.terminal.set_steps(self.steps);
+
+        let outcome = match get_cached_instruction(&mut self.storage, self.ir) {
+            Ok(ins) => ins.exec(&mut self.ir, &mut self.storage, &mut self.terminal),
+            Err(e) => StepOutcome::Fault(e),
+        };
+        self.last_write = self.storage.mem.take_last_write();
+        if matches!(outcome, StepOutcome::Continue | StepOutcome::Halted) {
+            self.steps += 1;
+            if let Some(pre_state) = pre_state {
+                let delta = self.delta(&pre_state);
+                self.history.push(delta);
+            }
+        }
+        outcome
+    }
+
+    // Memory write done by the last executed instruction: Address and previous value.
+    pub fn last_write(&self) -> Option<(u16, u16)> {
+        self.last_write
+    }
+
+    fn pre_state(&self) -> PreState {
+        PreState {
+            ir: self.ir,
+            regs: self.storage.regs.to_array(),
+            stack_top: self.storage.stack.last().copied(),
+            stack_len: self.storage.stack.len(),
+            next_input: self.terminal.input().chars().next(),
+            input_len: self.terminal.input().len(),
+            output_len: self.terminal.output_len(),
+        }
+    }
+
+    // What changed since the pre-state.
+    fn delta(&self, pre: &PreState) -> Delta {
+        let regs = self.storage.regs.to_array();
+        let stack_len = self.storage.stack.len();
+        Delta {
+            ir: pre.ir,
+            reg_write: (0..8)
+                .find(|r| regs[*r] != pre.regs[*r])
+                .map(|r| (r, pre.regs[r])),
+            mem_write: self.last_write,
+            stack: if stack_len > pre.stack_len {
+                StackDelta::Pushed
+            } else if stack_len < pre.stack_len {
+                StackDelta::Popped(pre.stack_top.unwrap())
+            } else {
+                StackDelta::Unchanged
+            },
+            input_read: if self.terminal.input().len() < pre.input_len {
+                pre.next_input
+            } else {
+                None
+            },
+            output_written: self.terminal.output_len() > pre.output_len,
+            edit: false,
+        }
+    }
+
+    // Debugger edit, undone by going back like an instruction.
+    fn edit(&mut self, reg_write: Option<(usize, u16)>, mem_write: Option<(u16, u16)>) {
+        self.history.push(Delta {
+            ir: self.ir,
+            reg_write,
+            mem_write,
+            stack: StackDelta::Unchanged,
+            input_read: None,
+            output_written: false,
+            edit: true,
+        });
+    }
+
+    // Sets a register, recording it in the history.
+    pub fn set_register(&mut self, r: RegNb, val: u16) {
+        self.edit(Some((*r, self.storage.regs.to_array()[*r])), None);
+        self.storage.regs.set(r, val);
+    }
+
+    // Sets a memory word, recording it in the history.
+    pub fn set_memory(&mut self, a: u16, val: u16) {
+        self.edit(None, Some((a, self.storage.mem.read(a))));
+        self.storage.mem.write(a, val);
+    }
+
+    // How many instructions can be undone at most. 0 disables the history.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    // Undoes the last executed instruction. Returns false if there is no history.
+    pub fn step_back(&mut self) -> bool {
+        let Some(delta) = self.history.pop() else {
+            return false;
+        };
+        self.ir = delta.ir;
+        if let Some((r, val)) = delta.reg_write {
+            self.storage.regs.set(RegNb::new(r), val);
+        }
+        if let Some((a, val)) = delta.mem_write {
+            self.storage.mem.write(a, val);
+        }
+        match delta.stack {
+            StackDelta::Unchanged => {}
+            StackDelta::Pushed => {
+                self.storage.stack.pop();
+            }
+            StackDelta::Popped(val) => self.storage.stack.push(val),
+        }
+        if let Some(c) = delta.input_read {
+            self.terminal.unread(c);
+        }
+        if delta.output_written {
+            self.terminal.unwrite();
+        }
+        if !delta.edit {
+            self.steps -= 1;
+        }
+        true
+    }
+
+    // Runs until the program halts, faults or needs more input.
+    pub fn run_until_input(&mut self) -> ExitReason {
+        loop {
+            if let Some(reason) = ExitReason::from_outcome(self.step()) {
+                return reason;
+            }
+        }
+    }
+
+    // Runs at most n instructions. Returns None if the program can continue.
+    pub fn run_for(&mut self, n: u64) -> Option<ExitReason> {
+        for _ in 0..n {
+            if let Some(reason) = ExitReason::from_outcome(self.step()) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    // Gives a line of input to the program.
+    pub fn feed_line(&mut self, line: &str) {
+        self.terminal.push_input(line);
+        self.terminal.push_input("\n");
+    }
+
+    // Runs the program with the specified actions, feeding them one by one when it asks for input.
+    pub fn run_actions(&mut self, actions: &[&str]) -> ExitReason {
+        let mut actions = actions.iter();
+        loop {
+            match self.run_until_input() {
+                ExitReason::InputExhausted => match actions.next() {
+                    Some(action) => self.feed_line(action),
+                    None => return ExitReason::InputExhausted,
+                },
+                reason => return reason,
+            }
+        }
+    }
+
+    // Get all that the program wrote, and clears it.
+    pub fn take_output(&mut self) -> String {
+        self.terminal.flush_out()
+    }
+
+    // Captures the full state of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ir: self.ir,
+            steps: self.steps,
+            regs: self.storage.regs.to_array(),
+            stack: self.storage.stack.clone(),
+            mem: self.storage.mem.words().to_vec(),
+            input: self.terminal.input().to_string(),
+        }
+    }
+
+    // Puts the machine back in the state of the snapshot.
+    // Output already produced is kept, history is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.history.clear();
+        self.ir = snapshot.ir;
+        self.steps = snapshot.steps;
+        self.storage.regs = Registers::from_array(snapshot.regs);
+        self.storage.stack = snapshot.stack.clone();
+        self.storage.mem.load_words(&snapshot.mem);
+        self.terminal.set_input(&snapshot.input);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::error::VmErrorKind;
+    use crate::vm::program::ADDRESS_SPACE;
+
+    fn vm(words: Vec<u16>) -> Vm {
+        Vm::new(&Program::from_words(words).unwrap())
+    }
+
+    #[test]
+    fn test_spec_sample() {
+        // add r0 r1 4, out r0
+        let mut vm = vm(vec![9, 32768, 32769, 4, 19, 32768]);
+        vm.storage_mut().regs.set(RegNb::new(1), 61);
+        // No halt instruction, but memory is zeroed after the program.
+        assert_eq!(vm.run_until_input(), ExitReason::Halted);
+        assert_eq!(vm.take_output(), "A");
+        assert_eq!(vm.steps(), 3);
+    }
+
+    #[test]
+    fn test_run_for() {
+        // noop, noop, halt
+        let mut vm = vm(vec![21, 21, 0]);
+        assert_eq!(vm.run_for(2), None);
+        assert_eq!(vm.ir(), 2);
+        assert_eq!(vm.run_for(2), Some(ExitReason::Halted));
+    }
+
+    #[test]
+    fn test_run_actions() {
+        // in r0, out r0, jmp 0
+        let mut vm = vm(vec![20, 32768, 19, 32768, 6, 0]);
+        assert!(vm.wants_input());
+        assert_eq!(vm.run_actions(&["hi", "yo"]), ExitReason::InputExhausted);
+        assert_eq!(vm.take_output(), "hi\nyo\n");
+    }
+
+    #[test]
+    fn test_snapshot() {
+        // in r0, out r0, jmp 0
+        let mut vm = vm(vec![20, 32768, 19, 32768, 6, 0]);
+        vm.feed_line("ab");
+        vm.run_for(3);
+        let snapshot = vm.snapshot();
+        assert_eq!(vm.run_until_input(), ExitReason::InputExhausted);
+        assert_eq!(vm.take_output(), "ab\n");
+
+        vm.restore(&snapshot);
+        assert_eq!(vm.steps(), 3);
+        assert_eq!(vm.terminal().input(), "b\n");
+        assert_eq!(vm.run_until_input(), ExitReason::InputExhausted);
+        assert_eq!(vm.take_output(), "b\n");
+    }
+
+    #[test]
+    fn test_step_back() {
+        // in r0, push r0, wmem 100 r0, out r0, pop r1, call 20, ... 20: ret
+        let mut words = vec![
+            20, 32768, 2, 32768, 16, 100, 32768, 19, 32768, 3, 32769, 17, 20,
+        ];
+        words.resize(20, 21);
+        words.push(18);
+        let mut vm = vm(words);
+        vm.set_history_capacity(100);
+        vm.feed_line("x");
+        let initial = vm.snapshot();
+
+        vm.run_for(7);
+        assert_eq!(vm.ir(), 13);
+        assert_eq!(vm.storage().mem.read(100), 'x' as u16);
+        assert_eq!(vm.storage().regs.to_array()[1], 'x' as u16);
+        assert_eq!(vm.take_output(), "x");
+        assert_eq!(vm.history_len(), 7);
+
+        while vm.step_back() {}
+        assert_eq!(vm.snapshot(), initial);
+    }
+
+    #[test]
+    fn test_step_back_edits() {
+        // set r0 1, set r0 2
+        let mut vm = vm(vec![1, 32768, 1, 1, 32768, 2]);
+        vm.set_history_capacity(100);
+        vm.step();
+        vm.set_register(RegNb::new(0), 7);
+        vm.set_memory(100, 8);
+        vm.step();
+        assert_eq!(vm.storage().regs.get(RegNb::new(0)), 2);
+
+        assert!(vm.step_back());
+        assert_eq!(vm.storage().regs.get(RegNb::new(0)), 7);
+        assert!(vm.step_back());
+        assert_eq!(vm.storage().mem.read(100), 0);
+        assert!(vm.step_back());
+        assert_eq!(vm.storage().regs.get(RegNb::new(0)), 1);
+        assert_eq!(vm.steps(), 1);
+        assert!(vm.step_back());
+        assert_eq!(vm.storage().regs.get(RegNb::new(0)), 0);
+        assert_eq!(vm.ir(), 0);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // out 'a', eq r1 r1 0, wmem 1 'b', jt r1 0, halt
+        let words = vec![19, 97, 4, 32769, 32769, 0, 16, 1, 98, 7, 32769, 0, 0];
+        let mut vm = vm(words);
+        assert_eq!(vm.run_until_input(), ExitReason::Halted);
+        // The cached "out 'a'" was dropped when its operand was written.
+        assert_eq!(vm.take_output(), "ab");
+    }
+
+    #[test]
+    fn test_fault() {
+        // pop r0 with an empty stack
+        let mut vm = vm(vec![3, 32768]);
+        let reason = vm.run_until_input();
+        assert!(matches!(reason, ExitReason::Fault(e) if e.kind == VmErrorKind::EmptyStack));
+    }
+
+    #[test]
+    fn test_decoding_fault() {
+        // noop, then an invalid opcode
+        let reason = vm(vec![21, 22]).run_until_input();
+        assert!(
+            matches!(reason, ExitReason::Fault(e) if e.kind == VmErrorKind::InvalidOpcode(22) && e.address == 1)
+        );
+
+        // set with a literal value instead of a register
+        let reason = vm(vec![1, 5, 0]).run_until_input();
+        assert!(
+            matches!(reason, ExitReason::Fault(e) if e.kind == VmErrorKind::InvalidRegister(5))
+        );
+    }
+
+    #[test]
+    fn test_address_fault() {
+        // rmem r0 6, rmem r1 r0, with 32770 as data: Reading through a register above the
+        // address space.
+        let reason = vm(vec![15, 32768, 6, 15, 32769, 32768, 32770]).run_until_input();
+        assert!(
+            matches!(reason, ExitReason::Fault(e) if e.kind == VmErrorKind::InvalidAddress(32770) && e.address == 3)
+        );
+
+        // jmp 32767, where a noop runs off the end of the address space.
+        let mut words = vec![6, 32767];
+        words.resize(ADDRESS_SPACE, 21);
+        let reason = vm(words).run_until_input();
+        assert!(
+            matches!(reason, ExitReason::Fault(e) if e.kind == VmErrorKind::InvalidAddress(32768))
+        );
+    }
+}
@@ -1,10 +1,24 @@
+mod breakpoints;
 mod debugger;
-mod instructions;
 mod intreg;
 
+pub mod assembler;
 pub mod decompiler;
+pub mod error;
+pub mod history;
+pub mod instructions;
+pub mod machine;
+pub mod profiler;
+pub mod program;
 pub mod run;
+pub mod search;
+pub mod snapshot;
 // Access to register and storage is needed for patching the binary
 pub mod register;
 pub mod storage;
+pub mod strings;
 pub mod terminal;
+pub mod tracer;
+pub mod translator;
+
+pub use machine::Vm;
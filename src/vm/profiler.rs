@@ -0,0 +1,277 @@
+//! Instruction-level profiler.
+//!
+//! Counts executions per address, and per function: A function is the target of a `call`, and
+//! lasts until the stack goes back below the return address it pushed. Exclusive counts are
+//! the instructions of the function itself, inclusive counts add the functions it calls.
+//! Recursive calls are only counted once in the inclusive count.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::vm::instructions::{get_instruction, Instruction, StepOutcome};
+use crate::vm::machine::Vm;
+use crate::vm::program::ADDRESS_SPACE;
+use crate::vm::storage::Storage;
+
+// Number of lines of each part of the report.
+const REPORT_TOP: usize = 20;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct FunctionStats {
+    calls: u64,
+    inclusive: u64,
+    exclusive: u64,
+}
+
+#[derive(Debug)]
+struct Frame {
+    function: u16,
+    // VM stack length once the return address was pushed.
+    stack_len: usize,
+    // Total instructions when the function was entered.
+    entry_total: u64,
+    // False if the function was already running, for a recursive call.
+    outermost: bool,
+    // Node of the call stack in the tree.
+    node: usize,
+}
+
+// Node of the tree of all the call stacks seen.
+#[derive(Debug)]
+struct StackNode {
+    function: u16,
+    parent: Option<usize>,
+    // Instructions executed with exactly this call stack.
+    count: u64,
+}
+
+#[derive(Debug)]
+pub struct Profiler {
+    total: u64,
+    address_counts: Vec<u64>,
+    functions: HashMap<u16, FunctionStats>,
+    frames: Vec<Frame>,
+    // How many frames of each function are on the stack.
+    active: HashMap<u16, u32>,
+    nodes: Vec<StackNode>,
+    children: HashMap<(usize, u16), usize>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            total: 0,
+            address_counts: vec![0; ADDRESS_SPACE],
+            functions: HashMap::new(),
+            frames: Vec::new(),
+            active: HashMap::new(),
+            nodes: Vec::new(),
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl Profiler {
+    // Executes one instruction with `exec`, and counts it.
+    pub fn step(&mut self, vm: &mut Vm, exec: impl FnOnce(&mut Vm) -> StepOutcome) -> StepOutcome {
+        if self.frames.is_empty() {
+            // Whatever is running when profiling starts is the root.
+            self.enter(vm.ir(), vm.storage().stack.len(), None);
+        }
+        let ir = vm.ir();
+        let is_call = vm
+            .current_instruction()
+            .is_ok_and(|ins| ins.name() == "call");
+
+        let outcome = exec(vm);
+        if !matches!(outcome, StepOutcome::Continue | StepOutcome::Halted) {
+            return outcome;
+        }
+
+        self.total += 1;
+        self.address_counts[ir as usize] += 1;
+        let frame = self.frames.last().unwrap();
+        self.functions.entry(frame.function).or_default().exclusive += 1;
+        self.nodes[frame.node].count += 1;
+
+        let stack_len = vm.storage().stack.len();
+        if is_call {
+            let parent = self.frames.last().map(|f| f.node);
+            self.enter(vm.ir(), stack_len, parent);
+        } else {
+            // Functions are done once their return address is popped, by ret or otherwise.
+            while self.frames.len() > 1 && self.frames.last().unwrap().stack_len > stack_len {
+                self.leave();
+            }
+        }
+        outcome
+    }
+
+    fn enter(&mut self, function: u16, stack_len: usize, parent: Option<usize>) {
+        let node = match parent.and_then(|p| self.children.get(&(p, function))) {
+            Some(node) => *node,
+            None => {
+                self.nodes.push(StackNode {
+                    function,
+                    parent,
+                    count: 0,
+                });
+                let node = self.nodes.len() - 1;
+                if let Some(p) = parent {
+                    self.children.insert((p, function), node);
+                }
+                node
+            }
+        };
+
+        let active = self.active.entry(function).or_default();
+        *active += 1;
+        self.functions.entry(function).or_default().calls += 1;
+        self.frames.push(Frame {
+            function,
+            stack_len,
+            entry_total: self.total,
+            outermost: *active == 1,
+            node,
+        });
+    }
+
+    fn leave(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        *self.active.get_mut(&frame.function).unwrap() -= 1;
+        if frame.outermost {
+            self.functions.get_mut(&frame.function).unwrap().inclusive +=
+                self.total - frame.entry_total;
+        }
+    }
+
+    // Stats per function, including the functions still running.
+    fn function_stats(&self) -> HashMap<u16, FunctionStats> {
+        let mut functions = self.functions.clone();
+        for frame in self.frames.iter().filter(|f| f.outermost) {
+            functions.get_mut(&frame.function).unwrap().inclusive += self.total - frame.entry_total;
+        }
+        functions
+    }
+
+    pub fn report(&self, storage: &Storage) -> String {
+        let mut out = String::new();
+        let percent = |n: u64| 100.0 * n as f64 / self.total.max(1) as f64;
+        writeln!(out, "Instructions executed: {}", self.total).unwrap();
+
+        writeln!(out, "\nHot spots:\naddress\tcount\t%\tinstruction").unwrap();
+        let mut addresses: Vec<_> = (0..ADDRESS_SPACE)
+            .filter(|a| self.address_counts[*a] > 0)
+            .collect();
+        addresses.sort_by_key(|a| (std::cmp::Reverse(self.address_counts[*a]), *a));
+        for a in addresses.into_iter().take(REPORT_TOP) {
+            let count = self.address_counts[a];
+            let ins = get_instruction(storage, a as u16)
+                .map(|i| i.decompile().replace('\t', " "))
+                .unwrap_or_default();
+            writeln!(out, "{}\t{}\t{:.2}\t{}", a, count, percent(count), ins).unwrap();
+        }
+
+        writeln!(
+            out,
+            "\nFunctions:\nfunction\tcalls\tinclusive\t%\texclusive\t%"
+        )
+        .unwrap();
+        let mut functions: Vec<_> = self.function_stats().into_iter().collect();
+        functions.sort_by_key(|(f, s)| (std::cmp::Reverse(s.inclusive), *f));
+        for (f, s) in functions.into_iter().take(REPORT_TOP) {
+            writeln!(
+                out,
+                "fn_{}\t{}\t{}\t{:.2}\t{}\t{:.2}",
+                f,
+                s.calls,
+                s.inclusive,
+                percent(s.inclusive),
+                s.exclusive,
+                percent(s.exclusive)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // Call stacks with their instruction counts, one per line like "fn_0;fn_1745;fn_2147 1234",
+    // the format flamegraph tools read.
+    pub fn folded_stacks(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for node in self.nodes.iter().filter(|n| n.count > 0) {
+            let mut names = vec![format!("fn_{}", node.function)];
+            let mut parent = node.parent;
+            while let Some(p) = parent {
+                names.push(format!("fn_{}", self.nodes[p].function));
+                parent = self.nodes[p].parent;
+            }
+            names.reverse();
+            lines.push(format!("{} {}", names.join(";"), node.count));
+        }
+        lines.sort();
+        lines.iter().map(|l| format!("{}\n", l)).collect()
+    }
+
+    pub fn save_folded_stacks<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.folded_stacks())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::program::Program;
+
+    #[test]
+    fn test_profile() {
+        // 0: call 5, 2: call 5, 4: halt, 5: call 9, 7: noop, 8: ret, 9: ret
+        let program = Program::from_words(vec![17, 5, 17, 5, 0, 17, 9, 21, 18, 18]).unwrap();
+        let mut vm = Vm::new(&program);
+        let mut profiler = Profiler::default();
+        while profiler.step(&mut vm, Vm::step) == StepOutcome::Continue {}
+
+        assert_eq!(profiler.total, 11);
+        assert_eq!(profiler.address_counts[5], 2);
+        assert_eq!(profiler.address_counts[4], 1);
+
+        let stats = profiler.function_stats();
+        assert_eq!(stats[&0].calls, 1);
+        assert_eq!(stats[&0].inclusive, 11);
+        assert_eq!(stats[&0].exclusive, 3);
+        assert_eq!(stats[&5].calls, 2);
+        assert_eq!(stats[&5].inclusive, 8);
+        assert_eq!(stats[&5].exclusive, 6);
+        assert_eq!(stats[&9].inclusive, 2);
+
+        assert_eq!(
+            profiler.folded_stacks(),
+            "fn_0 3\nfn_0;fn_5 6\nfn_0;fn_5;fn_9 2\n"
+        );
+        let report = profiler.report(vm.storage());
+        assert!(report.contains("\n5\t2\t18.18\t5 call 9\n"));
+        assert!(report.contains("\nfn_5\t2\t8\t72.73\t6\t54.55\n"));
+    }
+
+    #[test]
+    fn test_recursion() {
+        // 0: set r0 3, 3: call 6, 5: halt, 6: jf r0 16, 9: add r0 r0 32767, 13: call 6,
+        // 15: ret, 16: ret
+        let program = Program::from_words(vec![
+            1, 32768, 3, 17, 6, 0, 8, 32768, 16, 9, 32768, 32768, 32767, 17, 6, 18, 18,
+        ])
+        .unwrap();
+        let mut vm = Vm::new(&program);
+        let mut profiler = Profiler::default();
+        while profiler.step(&mut vm, Vm::step) == StepOutcome::Continue {}
+
+        let stats = profiler.function_stats();
+        assert_eq!(stats[&6].calls, 4);
+        // Counted once, not once per level of recursion.
+        assert_eq!(stats[&6].inclusive, profiler.total - 3);
+        assert_eq!(stats[&6].exclusive, profiler.total - 3);
+    }
+}
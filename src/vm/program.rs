@@ -0,0 +1,168 @@
+//! Loading of program images.
+//!
+//! A program image is a list of little-endian 16-bit words. It can be loaded from a file,
+//! from raw bytes or built directly from words (handy for tests and patched images).
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Memory has a 15-bit address space.
+pub const ADDRESS_SPACE: usize = 32768;
+
+// True if the value can be used as a memory address. Registers may hold values up to 32775,
+// which are outside of the address space.
+pub fn is_address(a: u16) -> bool {
+    (a as usize) < ADDRESS_SPACE
+}
+
+// Numbers 0..32767 are literal values, 32768..32775 are registers. Anything above is invalid.
+pub const FIRST_REGISTER: u16 = 32768;
+pub const MAX_VALID_WORD: u16 = 32775;
+
+const CHALLENGE_BIN: &str = "resources/challenge.bin";
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    // A program image is made of 16-bit words, so it cannot have an odd number of bytes.
+    OddByteCount(usize),
+    InvalidWord { address: usize, value: u16 },
+    TooLarge(usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "Failed to read program: {}", e),
+            LoadError::OddByteCount(n) => write!(f, "Program has an odd number of bytes ({})", n),
+            LoadError::InvalidWord { address, value } => {
+                write!(f, "Invalid number {} at address {}", value, address)
+            }
+            LoadError::TooLarge(n) => write!(
+                f,
+                "Program has {} words, but address space is only {}",
+                n, ADDRESS_SPACE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+// A validated program image.
+#[derive(Debug, Clone)]
+pub struct Program {
+    words: Vec<u16>,
+}
+
+impl Program {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let bytes = fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        if !bytes.len().is_multiple_of(2) {
+            return Err(LoadError::OddByteCount(bytes.len()));
+        }
+        // Converting to u16 with safe code
+        let words = bytes
+            .chunks_exact(2)
+            .map(|a| u16::from_le_bytes([a[0], a[1]]))
+            .collect();
+        Self::from_words(words)
+    }
+
+    pub fn from_words(words: Vec<u16>) -> Result<Self, LoadError> {
+        if words.len() > ADDRESS_SPACE {
+            return Err(LoadError::TooLarge(words.len()));
+        }
+        if let Some((address, value)) = words.iter().enumerate().find(|(_, v)| **v > MAX_VALID_WORD)
+        {
+            return Err(LoadError::InvalidWord {
+                address,
+                value: *value,
+            });
+        }
+        Ok(Self { words })
+    }
+
+    // The challenge binary shipped in the resources.
+    pub fn challenge() -> Result<Self, LoadError> {
+        Self::from_path(CHALLENGE_BIN)
+    }
+
+    pub fn words(&self) -> &[u16] {
+        &self.words
+    }
+
+    // The image as little-endian bytes, as it's stored in files.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        // The sample program from the spec.
+        let bytes = [
+            0x09, 0x00, 0x00, 0x80, 0x01, 0x80, 0x04, 0x00, 0x13, 0x00, 0x00, 0x80,
+        ];
+        let program = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(program.words(), &[9, 32768, 32769, 4, 19, 32768]);
+        assert_eq!(program.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_odd_byte_count() {
+        assert!(matches!(
+            Program::from_bytes(&[1, 0, 2]),
+            Err(LoadError::OddByteCount(3))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_word() {
+        assert!(matches!(
+            Program::from_words(vec![21, 32775, 32776]),
+            Err(LoadError::InvalidWord {
+                address: 2,
+                value: 32776
+            })
+        ));
+    }
+
+    #[test]
+    fn test_too_large() {
+        assert!(Program::from_words(vec![21; ADDRESS_SPACE]).is_ok());
+        assert!(matches!(
+            Program::from_words(vec![21; ADDRESS_SPACE + 1]),
+            Err(LoadError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_challenge() {
+        assert!(!Program::challenge().unwrap().is_empty());
+    }
+}
@@ -1,14 +1,19 @@
 use std::{fmt, ops::Deref};
 
+use crate::vm::error::VmErrorKind;
 use crate::vm::intreg::IntReg;
 
 // Number of a register. Enforces that the registers number is in correct range,
 // and provides helper functions such as more readable display.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct RegNb {
-    value: usize,
+    // A byte keeps decoded instructions small.
+    value: u8,
 }
 
+// Register numbers as usize, for Deref to return a reference to.
+const INDICES: [usize; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
 impl RegNb {
     pub fn is_valid(value: usize) -> bool {
         (0..=7).contains(&value)
@@ -16,14 +21,19 @@ impl RegNb {
 
     pub fn new(value: usize) -> Self {
         assert!(Self::is_valid(value));
-        Self { value }
+        Self { value: value as u8 }
     }
 }
 
-impl From<u16> for RegNb {
-    fn from(item: u16) -> Self {
-        assert!((32768..=32775).contains(&item));
-        RegNb::new((item - 32768) as usize)
+impl TryFrom<u16> for RegNb {
+    type Error = VmErrorKind;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
+        if (32768..=32775).contains(&item) {
+            Ok(RegNb::new((item - 32768) as usize))
+        } else {
+            Err(VmErrorKind::InvalidRegister(item))
+        }
     }
 }
 
@@ -31,7 +41,7 @@ impl Deref for RegNb {
     type Target = usize;
 
     fn deref(&self) -> &Self::Target {
-        &self.value
+        &INDICES[self.value as usize]
     }
 }
 
@@ -41,7 +51,7 @@ impl fmt::Display for RegNb {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct Registers {
     regs: [u16; 8],
 }
@@ -62,6 +72,14 @@ impl Registers {
         }
     }
 
+    pub fn to_array(&self) -> [u16; 8] {
+        self.regs
+    }
+
+    pub fn from_array(regs: [u16; 8]) -> Self {
+        Self { regs }
+    }
+
     #[cfg(test)]
     pub fn get(&self, i: RegNb) -> u16 {
         self.regs[*i]
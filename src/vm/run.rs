@@ -2,124 +2,274 @@ use std::collections::VecDeque;
 use std::io::{self, Write};
 
 use crate::maze::patch_code;
+use crate::vm::breakpoints::Breakpoints;
 use crate::vm::debugger;
-use crate::vm::instructions::get_instruction;
+use crate::vm::instructions::StepOutcome;
+use crate::vm::machine::{ExitReason, Vm};
+use crate::vm::profiler::Profiler;
+use crate::vm::program::Program;
+use crate::vm::snapshot::Snapshot;
 use crate::vm::storage::Storage;
-use crate::vm::terminal::Terminal;
+use crate::vm::terminal::{OutputHook, Terminal};
+use crate::vm::tracer::Tracer;
 
-use super::debugger::DebuggerActions;
-
-fn get_next_action(saved_actions: &mut VecDeque<&str>) -> Option<String> {
-    if !saved_actions.is_empty() {
-        let next_action = saved_actions.pop_front().unwrap();
-        Some(format!("{}\n", next_action))
-    } else {
-        None
-    }
-}
+use super::debugger::{BreakpointCmd, DebuggerActions};
 
 // Runs the program with the specified actions, returning the terminal output once done.
 #[cfg(test)]
 pub fn execute_actions(actions: &[&str]) -> String {
-    let mut storage = Storage::new();
-    let mut ir: u16 = 0;
-    let mut terminal = Terminal::new(false);
-    execute_actions_with_storage(actions, &mut ir, &mut storage, &mut terminal)
+    let mut vm = Vm::new(&Program::challenge().unwrap());
+    vm.run_actions(actions);
+    vm.take_output()
 }
 
-#[cfg(test)]
-pub fn execute_actions_with_storage(
-    actions: &[&str],
-    ir: &mut u16,
-    storage: &mut Storage,
-    terminal: &mut Terminal,
-) -> String {
-    let mut saved_actions: VecDeque<&str> = VecDeque::new();
-    saved_actions.extend(actions.iter().copied());
-
-    loop {
-        let ins = get_instruction(&storage, *ir);
+// How the interactive program should be run.
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    // Patch the teleporter check once the saved actions reach it.
+    pub patch_teleporter: bool,
+    // Print each instruction before executing it.
+    pub verbose: bool,
+    // Start in the debugger.
+    pub debug: bool,
+    // State to resume from, instead of starting the program from the beginning.
+    pub load_state: Option<Snapshot>,
+    // Number of executed instructions to keep for stepping backwards.
+    pub history: usize,
+    // Where to record the executed instructions.
+    pub trace: Option<Tracer>,
+    // Counts the executed instructions, reported at exit.
+    pub profile: Option<Profiler>,
+    // Where to write the call stacks of the profile, for flamegraph tools.
+    pub profile_folded: Option<String>,
+    // Sees the output as it's written.
+    pub output_hook: Option<Box<dyn OutputHook>>,
+    // Exit once the actions are run, instead of reading the user input.
+    pub stop_after_actions: bool,
+}
 
-        if ins.name() == "in" && terminal.is_input_empty() {
-            if let Some(action) = get_next_action(&mut saved_actions) {
-                terminal.set_input(&action);
-            } else {
-                break;
+// Runs the program, first executing the actions, then waiting for user input.
+pub fn execute_program(program: &Program, actions: &[&str], mut options: RunOptions) -> ExitReason {
+    let mut tracer = options.trace.take();
+    let mut profiler = options.profile.take();
+    let mut vm = Vm::with_terminal(program, Terminal::new(true));
+    if let Some(hook) = options.output_hook.take() {
+        vm.terminal_mut().set_output_hook(hook);
+    }
+    let reason = run(&mut vm, actions, &options, &mut tracer, &mut profiler);
+    if let Some(Err(e)) = tracer.as_mut().map(Tracer::finish) {
+        println!("Failed to write trace: {}", e);
+    }
+    if let Some(profiler) = profiler {
+        print!("{}", profiler.report(vm.storage()));
+        if let Some(path) = &options.profile_folded {
+            match profiler.save_folded_stacks(path) {
+                Ok(()) => println!("Call stacks written to {}", path),
+                Err(e) => println!("Failed to write call stacks: {}", e),
             }
         }
-
-        ins.exec(ir, storage, terminal);
     }
-
-    terminal.flush_out()
+    reason
 }
 
-// Runs the program, first executing the actions, then waiting for user input.
-pub fn execute_program(actions: &[&str]) {
-    let mut storage = Storage::new();
-    let mut terminal = Terminal::new(true);
-    let mut ir: u16 = 0;
+fn run(
+    vm: &mut Vm,
+    actions: &[&str],
+    options: &RunOptions,
+    tracer: &mut Option<Tracer>,
+    profiler: &mut Option<Profiler>,
+) -> ExitReason {
+    vm.set_history_capacity(options.history);
+    if let Some(snapshot) = &options.load_state {
+        vm.restore(snapshot);
+    }
 
     let mut saved_actions: VecDeque<&str> = VecDeque::new();
     saved_actions.extend(actions.iter().copied());
 
-    let mut verbose = false;
-    let mut set_breakpoint: Option<u16> = None;
+    let mut verbose = options.verbose;
+    let mut breakpoints = Breakpoints::default();
+    // Address the debugger was left at, so that we don't stop again at the same breakpoint.
+    let mut resumed_at: Option<u16> = None;
+
+    if options.debug {
+        vm.terminal_mut().set_interactive_mode();
+    }
 
     loop {
-        let ins = get_instruction(&storage, ir);
         if verbose {
-            println!("[{}] {}", ir, ins);
+            match vm.current_instruction() {
+                Ok(ins) => println!("[{}] {}", vm.ir(), ins),
+                Err(e) => return ExitReason::Fault(e),
+            }
         }
 
-        if ins.name() == "in" && terminal.is_input_empty() {
-            if let Some(action) = get_next_action(&mut saved_actions) {
+        if vm.wants_input() {
+            if let Some(action) = saved_actions.pop_front() {
                 // Patch the program with the correct code and to by-pass the check.
                 // We cannot patch the code too early, so wait until it's time.
-                if action.trim() == "look strange book" {
-                    patch_code::patch(&mut storage);
+                if options.patch_teleporter && action.trim() == "look strange book" {
+                    if let Err(e) = patch_code::patch(vm.storage_mut()) {
+                        println!("Teleporter not patched: {}", e);
+                    }
                 }
 
-                print!("{}", action);
-                terminal.set_input(&action);
+                println!("{}", action);
+                vm.feed_line(action);
+            } else if options.stop_after_actions && !vm.terminal().is_interactive_mode() {
+                return ExitReason::InputExhausted;
             }
         }
 
-        if let Some(breakpoint) = set_breakpoint {
-            if breakpoint == ir {
-                terminal.set_interactive_mode();
-                println!("Stopped at breakpoint {}", breakpoint);
+        if resumed_at.take() != Some(vm.ir()) {
+            if let Some(bp) = breakpoints.check_breakpoint(vm.ir(), vm.storage()) {
+                vm.terminal_mut().set_interactive_mode();
+                println!(
+                    "Stopped at breakpoint {} at {} (hit {} times)",
+                    bp.id, bp.address, bp.hits
+                );
             }
         }
-        if !terminal.is_interactive_mode() {
-            ins.exec(&mut ir, &mut storage, &mut terminal);
+        if !vm.terminal().is_interactive_mode() {
+            let outcome = step(vm, &mut breakpoints, tracer, profiler);
+            // Running out of input is fine if it's because the user entered the debugger.
+            if outcome != StepOutcome::WaitingForInput || !vm.terminal().is_interactive_mode() {
+                if let Some(reason) = ExitReason::from_outcome(outcome) {
+                    return reason;
+                }
+            }
         }
 
-        while terminal.is_interactive_mode() {
-            let debugger_actions = interactive_mode(ir, &storage);
+        while vm.terminal().is_interactive_mode() {
+            let debugger_actions = interactive_mode(vm.ir(), vm.storage(), &breakpoints);
             if let Some(true) = debugger_actions.quit {
-                terminal.quit_interactive_mode();
+                vm.terminal_mut().quit_interactive_mode();
+                resumed_at = Some(vm.ir());
             }
             if let Some(is_verbose) = debugger_actions.verbose {
                 verbose = is_verbose;
             }
-            set_breakpoint = debugger_actions.set_breakpoint;
-            if let Some(true) = debugger_actions.clear_breakpoint {
-                set_breakpoint = None;
+            if let Some(cmd) = debugger_actions.breakpoint {
+                update_breakpoints(&mut breakpoints, cmd, vm.storage());
             }
             if let Some((reg_nb, val)) = debugger_actions.set_register {
-                storage.regs.set(reg_nb, val);
+                vm.set_register(reg_nb, val);
                 println!("Register {} set to {}", reg_nb, val);
             }
             if let Some((a, val)) = debugger_actions.set_memory {
-                storage.mem.write(a, val);
+                vm.set_memory(a, val);
                 println!("Memory at {} set to {}", a, val);
             }
+            if let Some(n) = debugger_actions.step {
+                for _ in 0..n {
+                    if let Some(reason) =
+                        ExitReason::from_outcome(step(vm, &mut breakpoints, tracer, profiler))
+                    {
+                        println!("{}", reason);
+                        break;
+                    }
+                }
+                show_position(vm);
+            }
+            if let Some(n) = debugger_actions.step_back {
+                let undone = (0..n).take_while(|_| vm.step_back()).count();
+                println!("Went back {} instructions", undone);
+                show_position(vm);
+            }
+            if let Some(true) = debugger_actions.reverse_continue {
+                let mut undone = 0;
+                while vm.step_back() {
+                    undone += 1;
+                    if let Some(bp) = breakpoints.matching(vm.ir(), vm.storage()) {
+                        println!("Stopped at breakpoint {} at {}", bp.id, bp.address);
+                        break;
+                    }
+                }
+                println!("Went back {} instructions", undone);
+                show_position(vm);
+            }
+            if let Some(n) = debugger_actions.history_capacity {
+                vm.set_history_capacity(n);
+                println!("Keeping history of {} instructions", n);
+            }
+            if let Some(path) = debugger_actions.save_state {
+                match vm.snapshot().save(&path) {
+                    Ok(()) => println!("State saved to {}", path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if let Some(path) = debugger_actions.load_state {
+                match Snapshot::load(&path) {
+                    Ok(snapshot) => {
+                        vm.restore(&snapshot);
+                        println!("State loaded from {}", path);
+                    }
+                    Err(e) => println!("{}", e),
+                }
+            }
         }
     }
 }
 
-fn interactive_mode(ir: u16, storage: &Storage) -> DebuggerActions {
+// Executes one instruction, entering the debugger if it triggers a watchpoint.
+fn step(
+    vm: &mut Vm,
+    breakpoints: &mut Breakpoints,
+    tracer: &mut Option<Tracer>,
+    profiler: &mut Option<Profiler>,
+) -> StepOutcome {
+    let mut exec = |vm: &mut Vm| match tracer {
+        Some(tracer) => tracer.step(vm),
+        None => vm.step(),
+    };
+    let outcome = match profiler {
+        Some(profiler) => profiler.step(vm, exec),
+        None => exec(vm),
+    };
+    let hits = breakpoints.check_watchpoints(vm.storage(), vm.last_write());
+    if !hits.is_empty() {
+        for hit in hits {
+            println!("{}", hit);
+        }
+        vm.terminal_mut().set_interactive_mode();
+    }
+    outcome
+}
+
+fn update_breakpoints(breakpoints: &mut Breakpoints, cmd: BreakpointCmd, storage: &Storage) {
+    let found = match cmd {
+        BreakpointCmd::Add(address, condition) => {
+            let id = breakpoints.add_breakpoint(address, condition);
+            println!("Breakpoint {} set at {}", id, address);
+            true
+        }
+        BreakpointCmd::Watch(target, kind) => {
+            let id = breakpoints.add_watchpoint(target, kind, storage);
+            println!("Watchpoint {} set", id);
+            true
+        }
+        BreakpointCmd::Enable(id) => breakpoints.set_enabled(id, true),
+        BreakpointCmd::Disable(id) => breakpoints.set_enabled(id, false),
+        BreakpointCmd::Delete(id) => breakpoints.delete(id),
+        BreakpointCmd::Clear => {
+            breakpoints.clear();
+            println!("All breakpoints deleted");
+            true
+        }
+    };
+    if !found {
+        println!("No such breakpoint");
+    }
+}
+
+fn show_position(vm: &Vm) {
+    match vm.current_instruction() {
+        Ok(ins) => println!("[{}] {}", vm.ir(), ins),
+        Err(e) => println!("{}", e),
+    }
+}
+
+fn interactive_mode(ir: u16, storage: &Storage, breakpoints: &Breakpoints) -> DebuggerActions {
     print!("> ");
     let _ = io::stdout().flush();
     let mut buf = String::new();
@@ -127,5 +277,5 @@ fn interactive_mode(ir: u16, storage: &Storage) -> DebuggerActions {
         .read_line(&mut buf)
         .expect("Failed to read input");
 
-    debugger::exec_debug_cmd(buf.trim(), ir, storage)
+    debugger::exec_debug_cmd(buf.trim(), ir, storage, breakpoints)
 }
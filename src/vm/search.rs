@@ -0,0 +1,137 @@
+//! Search of the register values that make a routine give an expected result, like the code
+//! the teleporter checks.
+//!
+//! The routine is translated, then run for each candidate value on its own copy of the storage,
+//! in parallel. Calls are memoized when the routine doesn't access memory.
+
+use std::ops::RangeInclusive;
+
+use rayon::prelude::*;
+
+use super::register::RegNb;
+use super::storage::Storage;
+use super::translator::{translate, ExecError, Memo, TranslateError};
+
+#[derive(Debug, Clone)]
+pub struct InputSearch {
+    // Address of the routine.
+    pub entry: u16,
+    // Registers set before each run.
+    pub registers: Vec<(RegNb, u16)>,
+    // Register the candidates are tried in.
+    pub register: RegNb,
+    pub candidates: RangeInclusive<u16>,
+    // Instructions after which a run is given up.
+    pub max_steps: u64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchReport {
+    // Candidates for which the registers after the routine match, in order.
+    pub matches: Vec<u16>,
+    // Candidates whose run failed, like reaching the step limit.
+    pub failures: Vec<(u16, ExecError)>,
+}
+
+impl InputSearch {
+    // Runs the routine from the state of the storage for each candidate, and reports the ones
+    // for which the predicate holds on the registers after it returns.
+    pub fn run<P>(&self, storage: &Storage, predicate: P) -> Result<SearchReport, TranslateError>
+    where
+        P: Fn(&[u16; 8]) -> bool + Sync,
+    {
+        let function = translate(&storage.mem, self.entry)?;
+        let mut sandbox = storage.clone();
+        // The routine doesn't need the VM's cache, and it's a lot to copy for each candidate.
+        sandbox.mem.set_instruction_cache(false);
+        for (r, v) in &self.registers {
+            sandbox.regs.set(*r, *v);
+        }
+
+        let outcomes: Vec<(u16, Result<bool, ExecError>)> = self
+            .candidates
+            .clone()
+            .into_par_iter()
+            .map(|candidate| {
+                let mut st = sandbox.clone();
+                st.regs.set(self.register, candidate);
+                let mut memo = Memo::new();
+                let outcome = function
+                    .call_memoized(&mut st, Some(self.max_steps), &mut memo)
+                    .map(|_| predicate(&st.regs.to_array()));
+                (candidate, outcome)
+            })
+            .collect();
+
+        let mut report = SearchReport::default();
+        for (candidate, outcome) in outcomes {
+            match outcome {
+                Ok(true) => report.matches.push(candidate),
+                Ok(false) => {}
+                Err(e) => report.failures.push((candidate, e)),
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::program::Program;
+
+    #[test]
+    fn test_search() {
+        // 0: mult r0 r1 r1, 4: ret: Which r1 gives 49 (or 49 + 32768)?
+        let storage =
+            Storage::from_program(&Program::from_words(vec![10, 32768, 32769, 32769, 18]).unwrap());
+        let search = InputSearch {
+            entry: 0,
+            registers: vec![],
+            register: RegNb::new(1),
+            candidates: 0..=32767,
+            max_steps: 10,
+        };
+        let report = search.run(&storage, |regs| regs[0] == 49).unwrap();
+        assert_eq!(report.matches, [7, 16377, 16391, 32761]);
+        assert!(report.failures.is_empty());
+    }
+
+    #[test]
+    fn test_step_limit() {
+        // 0: jt r1 0, 3: ret: Loops forever unless r1 is 0.
+        let storage = Storage::from_program(&Program::from_words(vec![7, 32769, 0, 18]).unwrap());
+        let search = InputSearch {
+            entry: 0,
+            registers: vec![],
+            register: RegNb::new(1),
+            candidates: 0..=2,
+            max_steps: 1000,
+        };
+        let report = search.run(&storage, |_| true).unwrap();
+        assert_eq!(report.matches, [0]);
+        assert_eq!(
+            report.failures,
+            [
+                (1, ExecError::StepLimit(1000)),
+                (2, ExecError::StepLimit(1000))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_teleporter() {
+        // The check of the teleporter code, without any port of it: r0 must be 6.
+        let storage = Storage::from_program(&Program::challenge().unwrap());
+        let search = InputSearch {
+            entry: 6049,
+            registers: vec![(RegNb::new(0), 4), (RegNb::new(1), 1)],
+            register: RegNb::new(7),
+            candidates: 25730..=25737,
+            max_steps: 100_000_000,
+        };
+        let report = search.run(&storage, |regs| regs[0] == 6).unwrap();
+        assert_eq!(report.matches, [25734]);
+        assert!(report.failures.is_empty());
+    }
+}
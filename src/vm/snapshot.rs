@@ -0,0 +1,222 @@
+//! Saving and restoring the full state of the VM.
+//!
+//! Binary format, all numbers little-endian:
+//!
+//! ```text
+//! magic       4 bytes, "SYNS"
+//! version     u16
+//! ir          u16
+//! steps       u64
+//! registers   8 x u16
+//! stack       u32 length, then the words
+//! memory      u32 length, then the words (trailing zeros are not stored)
+//! input       u32 length, then the pending terminal input as UTF-8
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::vm::program::ADDRESS_SPACE;
+
+const MAGIC: &[u8; 4] = b"SYNS";
+const VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Failed to access snapshot: {}", e),
+            SnapshotError::BadMagic => write!(f, "Not a snapshot file"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported snapshot version {}", v)
+            }
+            SnapshotError::Truncated => write!(f, "Snapshot is truncated"),
+            SnapshotError::Invalid(what) => write!(f, "Invalid snapshot: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+// Full state of the machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub ir: u16,
+    pub steps: u64,
+    pub regs: [u16; 8],
+    pub stack: Vec<u16>,
+    pub mem: Vec<u16>,
+    // Terminal input not read yet by the program.
+    pub input: String,
+}
+
+// Reads the serialized snapshot piece by piece.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < n {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn words(&mut self) -> Result<Vec<u16>, SnapshotError> {
+        let len = self.u32()? as usize;
+        if len > ADDRESS_SPACE * 2 {
+            return Err(SnapshotError::Invalid("too many words"));
+        }
+        (0..len).map(|_| self.u16()).collect()
+    }
+}
+
+fn push_words(out: &mut Vec<u8>, words: &[u16]) {
+    out.extend((words.len() as u32).to_le_bytes());
+    out.extend(words.iter().flat_map(|w| w.to_le_bytes()));
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(MAGIC);
+        out.extend(VERSION.to_le_bytes());
+        out.extend(self.ir.to_le_bytes());
+        out.extend(self.steps.to_le_bytes());
+        out.extend(self.regs.iter().flat_map(|r| r.to_le_bytes()));
+        push_words(&mut out, &self.stack);
+
+        let mem_len = self.mem.iter().rposition(|w| *w != 0).map_or(0, |p| p + 1);
+        push_words(&mut out, &self.mem[..mem_len]);
+
+        out.extend((self.input.len() as u32).to_le_bytes());
+        out.extend(self.input.as_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let ir = reader.u16()?;
+        let steps = reader.u64()?;
+        let mut regs = [0; 8];
+        for r in regs.iter_mut() {
+            *r = reader.u16()?;
+        }
+        let stack = reader.words()?;
+        let mut mem = reader.words()?;
+        if mem.len() > ADDRESS_SPACE {
+            return Err(SnapshotError::Invalid("memory larger than address space"));
+        }
+        mem.resize(ADDRESS_SPACE, 0);
+
+        let input_len = reader.u32()? as usize;
+        let input = String::from_utf8(reader.take(input_len)?.to_vec())
+            .map_err(|_| SnapshotError::Invalid("input is not UTF-8"))?;
+
+        Ok(Self {
+            ir,
+            steps,
+            regs,
+            stack,
+            mem,
+            input,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut mem = vec![0; ADDRESS_SPACE];
+        mem[0] = 21;
+        mem[1000] = 32775;
+        Snapshot {
+            ir: 1000,
+            steps: 123456789,
+            regs: [1, 2, 3, 4, 5, 6, 7, 25734],
+            stack: vec![6049, 5513],
+            mem,
+            input: "go north\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let s = snapshot();
+        let bytes = s.to_bytes();
+        // Trailing zeros of memory are not stored.
+        assert!(bytes.len() < 2 * 1100);
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), s);
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes = snapshot().to_bytes();
+        assert!(matches!(
+            Snapshot::from_bytes(b"NOPE"),
+            Err(SnapshotError::BadMagic)
+        ));
+        assert!(matches!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        ));
+
+        let mut other_version = bytes.clone();
+        other_version[4] = 99;
+        assert!(matches!(
+            Snapshot::from_bytes(&other_version),
+            Err(SnapshotError::UnsupportedVersion(99))
+        ));
+    }
+}
@@ -1,49 +1,121 @@
-use std::fs;
-
+use crate::vm::instructions::Ins;
+use crate::vm::program::{Program, ADDRESS_SPACE};
 use crate::vm::register::Registers;
 
-fn load_bin() -> Vec<u16> {
-    let bytes = fs::read("resources/challenge.bin").unwrap();
-    // Converting to u16 with safe code
-    bytes
-        .chunks_exact(2)
-        .map(|a| u16::from_le_bytes([a[0], a[1]]))
-        .inspect(|v| assert!(*v < 32776)) // numbers 32776..65535 are invalid
-        .collect()
-}
-
 // The binary we are loading contains both the instructions and data.
 // In other words, it's a shared address space.
 pub struct Memory {
     mem: Vec<u16>,
+    // Size of the loaded program, the rest of the address space is zeroed.
+    program_len: usize,
+    // Address and previous value of the last write, for tools that need to track changes.
+    last_write: Option<(u16, u16)>,
+    cache_enabled: bool,
+    // Instructions decoded at each address, dropped when one of their words is written.
+    // Allocated when the first instruction is cached, so that copies of the memory that aren't
+    // run, like snapshots and searches, don't pay for it.
+    decoded: Vec<Option<Ins>>,
+}
+
+// The cache of decoded instructions isn't copied, it's rebuilt as the copy runs.
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            program_len: self.program_len,
+            last_write: self.last_write,
+            cache_enabled: self.cache_enabled,
+            decoded: Vec::new(),
+        }
+    }
 }
 
+// Most words an instruction takes, so a write can change the instructions up to this many
+// words before.
+const MAX_INSTRUCTION_LEN: usize = 4;
+
 impl Memory {
-    pub fn new() -> Self {
-        let bin = load_bin();
-        Self { mem: bin }
+    pub fn from_program(program: &Program) -> Self {
+        let mut mem = program.words().to_vec();
+        // A few extra words after the address space, so that instructions can always be decoded
+        // from a full slice, even at the very end of memory.
+        mem.resize(ADDRESS_SPACE + MAX_INSTRUCTION_LEN - 1, 0);
+        Self {
+            mem,
+            program_len: program.len(),
+            last_write: None,
+            cache_enabled: true,
+            decoded: Vec::new(),
+        }
     }
 
-    pub fn len(&self) -> u16 {
-        self.mem.len() as u16
+    pub fn len(&self) -> usize {
+        self.program_len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.program_len == 0
+    }
+
+    // Panics if the address is outside of the address space, callers check it with is_address.
     pub fn read(&self, a: u16) -> u16 {
         self.mem[a as usize]
     }
 
     pub fn write(&mut self, a: u16, val: u16) {
+        self.last_write = Some((a, self.mem[a as usize]));
         self.mem[a as usize] = val;
+        if !self.decoded.is_empty() {
+            let a = a as usize;
+            self.decoded[a.saturating_sub(MAX_INSTRUCTION_LEN - 1)..=a].fill(None);
+        }
+    }
+
+    // Returns the last write done since the previous call.
+    pub fn take_last_write(&mut self) -> Option<(u16, u16)> {
+        self.last_write.take()
+    }
+
+    // The whole address space.
+    pub fn words(&self) -> &[u16] {
+        &self.mem[..ADDRESS_SPACE]
+    }
+
+    // Replaces the content of the address space, the rest being zeroed.
+    pub fn load_words(&mut self, words: &[u16]) {
+        self.mem.fill(0);
+        self.mem[..words.len()].copy_from_slice(words);
+        self.decoded.fill(None);
+    }
+
+    pub fn cached_instruction(&self, a: u16) -> Option<Ins> {
+        self.decoded.get(a as usize).copied().flatten()
+    }
+
+    pub fn cache_instruction(&mut self, a: u16, ins: Ins) {
+        if !self.cache_enabled {
+            return;
+        }
+        if self.decoded.is_empty() {
+            self.decoded = vec![None; self.mem.len()];
+        }
+        self.decoded[a as usize] = Some(ins);
+    }
+
+    // Turns the cache of decoded instructions on or off, to compare the speed.
+    pub fn set_instruction_cache(&mut self, enabled: bool) {
+        self.cache_enabled = enabled;
+        self.decoded = Vec::new();
     }
 
     pub fn ins_slice(&self, from: u16) -> &[u16] {
         let f = from as usize;
-        let t = (self.mem.len() - 1).min(f + 4);
-        &self.mem[f..t]
+        &self.mem[f..f + 4]
     }
 }
 
 // Holder for all 3 storage regions.
+#[derive(Clone)]
 pub struct Storage {
     pub mem: Memory,
     pub regs: Registers,
@@ -51,9 +123,16 @@ pub struct Storage {
 }
 
 impl Storage {
+    // Storage loaded with the challenge binary.
+    #[cfg(test)]
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Self::from_program(&Program::challenge().expect("Failed to load challenge binary"))
+    }
+
+    pub fn from_program(program: &Program) -> Self {
         Self {
-            mem: Memory::new(),
+            mem: Memory::from_program(program),
             regs: Registers::new(),
             stack: Vec::new(),
         }
@@ -0,0 +1,214 @@
+//! Static extraction of the game text.
+//!
+//! Strings are length-prefixed: A word with the length, then one word per character.
+//! The text is hidden in two layers:
+//! - At startup, a routine decrypts the data with `mem[a] ^= (a * a) ^ key`.
+//! - Some messages, like the ones with codes, are xored again when printed: The caller passes
+//!   the string and a key to the "for each character" routine, with a callback that xors
+//!   each character with the key and prints it.
+//!
+//! The routines are found by matching their code, and their constants are read from it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::program::{Program, FIRST_REGISTER};
+
+// Matches any word in a pattern. The matched words are returned.
+const ANY: u16 = u16::MAX;
+
+const R0: u16 = FIRST_REGISTER;
+const R1: u16 = FIRST_REGISTER + 1;
+const R2: u16 = FIRST_REGISTER + 2;
+
+// xor r0 r1, which the VM doesn't have:
+// push r1, push r2, and r2 r0 r1, not r2 r2, or r0 r0 r1, and r0 r0 r2, pop r2, pop r1, ret
+const XOR_FN: [u16; 24] = [
+    2, R1, 2, R2, 12, R2, R0, R1, 14, R2, R2, 13, R0, R0, R1, 12, R0, R0, R2, 3, R2, 3, R1, 18,
+];
+
+// Decryption of the memory at startup, with the address of the xor function:
+// push r0, push r1, set r1 <start>, loop: rmem r0 r1, push r1, mult r1 r1 r1, call xor,
+// set r1 <key>, call xor, pop r1, wmem r1 r0, add r1 r1 1, eq r0 <end> r1, jf r0 loop,
+// pop r1, pop r0, ret
+fn decrypt_fn(xor: u16) -> Vec<u16> {
+    vec![
+        2, R0, 2, R1, 1, R1, ANY, 15, R0, R1, 2, R1, 10, R1, R1, R1, 17, xor, 1, R1, ANY, 17, xor,
+        3, R1, 16, R1, R0, 9, R1, R1, 1, 4, R0, ANY, R1, 8, R0, ANY, 3, R1, 3, R0, 18,
+    ]
+}
+
+// Callback printing a character xored with the key in r2:
+// push r1, set r1 r2, call xor, out r0, pop r1, ret
+fn xor_print_fn(xor: u16) -> Vec<u16> {
+    vec![2, R1, 1, R1, R2, 17, xor, 19, R0, 3, R1, 18]
+}
+
+// Printing of an encrypted string, the key being computed with an addition:
+// set r0 <string>, set r1 <callback>, add r2 <a> <b>, call <for each>
+fn xor_print_call(callback: u16) -> Vec<u16> {
+    vec![1, R0, ANY, 1, R1, callback, 9, R2, ANY, ANY, 17, ANY]
+}
+
+// Addresses where the pattern matches, with the words matched by ANY.
+fn find_pattern(mem: &[u16], pattern: &[u16]) -> Vec<(u16, Vec<u16>)> {
+    mem.windows(pattern.len())
+        .enumerate()
+        .filter(|(_, w)| w.iter().zip(pattern).all(|(m, p)| *p == ANY || m == p))
+        .map(|(a, w)| {
+            let captures = w
+                .iter()
+                .zip(pattern)
+                .filter(|(_, p)| **p == ANY)
+                .map(|(m, _)| *m)
+                .collect();
+            (a as u16, captures)
+        })
+        .collect()
+}
+
+// Address of the first match of a pattern without ANY.
+fn find_address(mem: &[u16], pattern: &[u16]) -> Option<u16> {
+    find_pattern(mem, pattern).first().map(|(a, _)| *a)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameString {
+    pub address: u16,
+    // Key the string is xored with when printed, if it's encrypted.
+    pub key: Option<u16>,
+    pub text: String,
+}
+
+impl fmt::Display for GameString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{:?}", self.address, self.text)?;
+        if let Some(key) = self.key {
+            write!(f, " (key {})", key)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_printable(w: u16) -> bool {
+    (32..127).contains(&w) || w == '\n' as u16
+}
+
+// The length-prefixed string at the address, xored with the key. None if it's not text.
+fn read_string(mem: &[u16], address: usize, key: u16) -> Option<String> {
+    let len = *mem.get(address)? as usize;
+    let words = mem.get(address + 1..address + 1 + len)?;
+    words
+        .iter()
+        .map(|w| w ^ key)
+        .all(is_printable)
+        .then(|| words.iter().map(|w| char::from((w ^ key) as u8)).collect())
+}
+
+// The memory once decrypted like the program does at startup.
+fn decrypted_memory(program: &Program) -> Vec<u16> {
+    let mut mem = program.words().to_vec();
+    let Some(xor) = find_address(&mem, &XOR_FN) else {
+        return mem;
+    };
+    if let Some((_, c)) = find_pattern(&mem, &decrypt_fn(xor)).first() {
+        let (start, key, end) = (c[0] as usize, c[1], c[2] as usize);
+        for (a, word) in mem.iter_mut().enumerate().take(end).skip(start) {
+            // mult is modulo 32768.
+            *word ^= ((a * a) % 32768) as u16 ^ key;
+        }
+    }
+    mem
+}
+
+// All the strings of the program: The encrypted ones found from where they are printed,
+// and the plain text ones by scanning the memory.
+pub fn extract_strings(program: &Program) -> Vec<GameString> {
+    let mem = decrypted_memory(program);
+    let mut strings = Vec::new();
+
+    if let Some(xor) = find_address(&mem, &XOR_FN) {
+        for (callback, _) in find_pattern(&mem, &xor_print_fn(xor)) {
+            for (_, c) in find_pattern(&mem, &xor_print_call(callback)) {
+                let key = c[1].wrapping_add(c[2]) % 32768;
+                if let Some(text) = read_string(&mem, c[0] as usize, key) {
+                    strings.push(GameString {
+                        address: c[0],
+                        key: Some(key),
+                        text,
+                    });
+                }
+            }
+        }
+    }
+    strings.sort_by_key(|s| s.address);
+    strings.dedup();
+
+    // Plain text strings, skipping the encrypted ones.
+    let encrypted: HashMap<usize, usize> = strings
+        .iter()
+        .map(|s| (s.address as usize, s.text.len()))
+        .collect();
+    let mut a = 0;
+    while a < mem.len() {
+        if let Some(len) = encrypted.get(&a) {
+            a += len + 1;
+            continue;
+        }
+        match read_string(&mem, a, 0) {
+            // Single characters are too likely to be something else.
+            Some(text) if text.len() > 1 => {
+                let len = text.len();
+                strings.push(GameString {
+                    address: a as u16,
+                    key: None,
+                    text,
+                });
+                a += len + 1;
+            }
+            _ => a += 1,
+        }
+    }
+    strings.sort_by_key(|s| s.address);
+    strings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codes::codes_check::code_number;
+
+    #[test]
+    fn test_plain_strings() {
+        // out 'A', halt, then "hi" and "x"
+        let program = Program::from_words(vec![19, 65, 0, 2, 104, 105, 1, 120]).unwrap();
+        let strings = extract_strings(&program);
+        assert_eq!(
+            strings,
+            [GameString {
+                address: 3,
+                key: None,
+                text: "hi".to_string()
+            }]
+        );
+        assert_eq!(strings[0].to_string(), "3\t\"hi\"");
+    }
+
+    #[test]
+    fn test_challenge() {
+        let strings = extract_strings(&Program::challenge().unwrap());
+        assert!(strings.contains(&GameString {
+            address: 6164,
+            key: None,
+            text: "Foothills".to_string()
+        }));
+        let self_test = strings.iter().find(|s| s.address == 26865).unwrap();
+        assert_eq!(self_test.key, Some(28533));
+        let code = self_test
+            .text
+            .strip_prefix("The self-test completion code is: ")
+            .unwrap();
+        assert_eq!(code_number(code.trim()), Some(2));
+        assert_eq!(strings.iter().filter(|s| s.key.is_some()).count(), 36);
+    }
+}
@@ -1,4 +1,22 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::io;
+use std::rc::Rc;
+
+// Sees each char written to the terminal, for tools that need to follow the output as it's
+// written.
+pub trait OutputHook: fmt::Debug {
+    // Char written while running the command at this index, None before the first one, at
+    // this step of the VM.
+    fn write(&mut self, c: char, command: Option<usize>, step: u64);
+}
+
+// A shared hook, so that its owner can still look at it while the terminal uses it.
+impl<H: OutputHook> OutputHook for Rc<RefCell<H>> {
+    fn write(&mut self, c: char, command: Option<usize>, step: u64) {
+        self.borrow_mut().write(c, command, step);
+    }
+}
 
 // A way to access the terminal from the code, which can also be used in tests.
 pub struct Terminal {
@@ -8,6 +26,11 @@ pub struct Terminal {
 
     input: String,
     interactive_mode: bool,
+
+    // Sees each char written, with the command and step writing it.
+    output_hook: Option<Box<dyn OutputHook>>,
+    lines_read: usize,
+    steps: u64,
 }
 
 impl Terminal {
@@ -18,6 +41,9 @@ impl Terminal {
             print,
             input: String::new(),
             interactive_mode: false,
+            output_hook: None,
+            lines_read: 0,
+            steps: 0,
         }
     }
 
@@ -27,18 +53,38 @@ impl Terminal {
             print!("{}", c);
         }
         self.output.push(c);
+        if let Some(hook) = &mut self.output_hook {
+            hook.write(c, self.lines_read.checked_sub(1), self.steps);
+        }
+    }
+
+    // Removes the last char written to terminal.
+    // Note that if it was printed to the real terminal, it stays there.
+    pub fn unwrite(&mut self) {
+        self.output.pop();
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.output.len()
     }
 
     // Read a char from terminal.
     // The terminal input is cached in `self.input`: If that is not empty, return the first char from it.
     // If it's empty, read from stdin and fill the cache with the read line.
-    // If the read line starts with '>', just return None.
+    // If the read line starts with '>', or if there is nothing more to read, just return None.
+    // When not connected to the real terminal, stdin isn't read and None is returned once the cache is empty.
     pub fn read(&mut self) -> Option<char> {
         if self.input.is_empty() {
+            if !self.print {
+                return None;
+            }
             let mut buf = String::new();
-            io::stdin()
+            let read = io::stdin()
                 .read_line(&mut buf)
                 .expect("Failed to read input");
+            if read == 0 {
+                return None;
+            }
             if buf.starts_with('>') {
                 self.interactive_mode = true;
                 return None;
@@ -46,11 +92,14 @@ impl Terminal {
                 self.input = buf;
             }
         }
-        Some(self.input.remove(0))
+        let c = self.input.remove(0);
+        if c == '\n' {
+            self.lines_read += 1;
+        }
+        Some(c)
     }
 
     // Get all that went to terminal, and clears it.
-    #[cfg(test)]
     pub fn flush_out(&mut self) -> String {
         let out = self.output.clone();
         self.output.clear();
@@ -62,6 +111,24 @@ impl Terminal {
         self.input = input.to_string();
     }
 
+    // Puts back a char that was read.
+    pub fn unread(&mut self, c: char) {
+        if c == '\n' {
+            self.lines_read -= 1;
+        }
+        self.input.insert(0, c);
+    }
+
+    // Add to what should be read from terminal.
+    pub fn push_input(&mut self, input: &str) {
+        self.input.push_str(input);
+    }
+
+    // What is left to be read.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
     pub fn is_input_empty(&self) -> bool {
         self.input.is_empty()
     }
@@ -77,4 +144,19 @@ impl Terminal {
     pub fn quit_interactive_mode(&mut self) {
         self.interactive_mode = false;
     }
+
+    // Passes what is written to the hook from now on.
+    pub fn set_output_hook(&mut self, hook: Box<dyn OutputHook>) {
+        self.output_hook = Some(hook);
+    }
+
+    // Number of lines of input read: The first command is line 0.
+    pub fn lines_read(&self) -> usize {
+        self.lines_read
+    }
+
+    // Step of the VM, for what is written next.
+    pub fn set_steps(&mut self, steps: u64) {
+        self.steps = steps;
+    }
 }
@@ -0,0 +1,220 @@
+//! Execution tracer, writing one JSON line per executed instruction.
+//!
+//! ```text
+//! {"step":1033,"ir":6049,"op":"jt","args":[32768,6057],"vals":[4,6057]}
+//! {"step":1034,"ir":6057,"op":"add","args":[32768,32768,32767],"vals":[4,4,32767],"reg":[0,3]}
+//! {"step":1035,"ir":1770,"op":"wmem","args":[32769,32768],"vals":[6090,30],"mem":[6090,30]}
+//! ```
+//!
+//! `args` are the operand words, `vals` the same with registers replaced by their value,
+//! `reg` and `mem` the register or memory written, with the new value. Steps count from the
+//! start of the program, so traces of two runs can be diffed.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::vm::instructions::{Instruction, StepOutcome};
+use crate::vm::machine::Vm;
+use crate::vm::program::{FIRST_REGISTER, MAX_VALID_WORD};
+
+// Which instructions are traced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    // Only instructions at addresses in this range, inclusive.
+    pub range: Option<(u16, u16)>,
+    // Only from this step on.
+    pub from_step: u64,
+    // At most this number of records.
+    pub limit: Option<u64>,
+}
+
+impl TraceFilter {
+    // Parses an address range like "6049-6089".
+    pub fn parse_range(s: &str) -> Option<(u16, u16)> {
+        let (from, to) = s.split_once('-')?;
+        let (from, to) = (from.parse().ok()?, to.parse().ok()?);
+        (from <= to).then_some((from, to))
+    }
+}
+
+pub struct Tracer<W: Write = BufWriter<File>> {
+    out: W,
+    filter: TraceFilter,
+    records: u64,
+    // First write error, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn create<P: AsRef<Path>>(path: P, filter: TraceFilter) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), filter))
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W, filter: TraceFilter) -> Self {
+        Self {
+            out,
+            filter,
+            records: 0,
+            error: None,
+        }
+    }
+
+    fn is_traced(&self, vm: &Vm) -> bool {
+        self.error.is_none()
+            && vm.steps() >= self.filter.from_step
+            && self.filter.limit.is_none_or(|limit| self.records < limit)
+            && self
+                .filter
+                .range
+                .is_none_or(|(from, to)| (from..=to).contains(&vm.ir()))
+    }
+
+    // Executes one instruction, recording it if it passes the filter.
+    pub fn step(&mut self, vm: &mut Vm) -> StepOutcome {
+        if !self.is_traced(vm) {
+            return vm.step();
+        }
+        let Ok(ins) = vm.current_instruction() else {
+            return vm.step();
+        };
+
+        let step = vm.steps();
+        let ir = vm.ir();
+        let regs = vm.storage().regs.to_array();
+        let args = vm.storage().mem.ins_slice(ir)[1..ins.offset() as usize].to_vec();
+        let vals: Vec<u16> = args
+            .iter()
+            .map(|a| match a {
+                FIRST_REGISTER..=MAX_VALID_WORD => regs[(*a - FIRST_REGISTER) as usize],
+                _ => *a,
+            })
+            .collect();
+
+        let outcome = vm.step();
+        if !matches!(outcome, StepOutcome::Continue | StepOutcome::Halted) {
+            return outcome;
+        }
+
+        let mut record = format!(
+            r#"{{"step":{},"ir":{},"op":"{}","args":{},"vals":{}"#,
+            step,
+            ir,
+            ins.name(),
+            json_list(&args),
+            json_list(&vals)
+        );
+        // Every register write is recorded, even one not changing the value.
+        if let Some(r) = ins.written_register() {
+            let val = vm.storage().regs.to_array()[*r];
+            record += &format!(r#","reg":[{},{}]"#, *r, val);
+        }
+        if let Some((a, _)) = vm.last_write() {
+            record += &format!(r#","mem":[{},{}]"#, a, vm.storage().mem.read(a));
+        }
+        record += "}";
+
+        if let Err(e) = writeln!(self.out, "{}", record) {
+            self.error = Some(e);
+        }
+        self.records += 1;
+        outcome
+    }
+
+    // Flushes the output, reporting the first write error if any.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    #[cfg(test)]
+    fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> fmt::Debug for Tracer<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("filter", &self.filter)
+            .field("records", &self.records)
+            .finish()
+    }
+}
+
+fn json_list(words: &[u16]) -> String {
+    let words: Vec<_> = words.iter().map(|w| w.to_string()).collect();
+    format!("[{}]", words.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::program::Program;
+
+    fn trace(words: Vec<u16>, filter: TraceFilter) -> Vec<String> {
+        let mut vm = Vm::new(&Program::from_words(words).unwrap());
+        let mut tracer = Tracer::new(Vec::new(), filter);
+        while tracer.step(&mut vm) == StepOutcome::Continue {}
+        tracer.finish().unwrap();
+        let out = String::from_utf8(tracer.into_inner()).unwrap();
+        out.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_records() {
+        // set r1 5, add r0 r1 1, wmem 100 r0, halt
+        let lines = trace(
+            vec![1, 32769, 5, 9, 32768, 32769, 1, 16, 100, 32768, 0],
+            TraceFilter::default(),
+        );
+        assert_eq!(
+            lines,
+            [
+                r#"{"step":0,"ir":0,"op":"set","args":[32769,5],"vals":[0,5],"reg":[1,5]}"#,
+                r#"{"step":1,"ir":3,"op":"add","args":[32768,32769,1],"vals":[0,5,1],"reg":[0,6]}"#,
+                r#"{"step":2,"ir":7,"op":"wmem","args":[100,32768],"vals":[100,6],"mem":[100,6]}"#,
+                r#"{"step":3,"ir":10,"op":"halt","args":[],"vals":[]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_value_write() {
+        // set r0 0, halt
+        let lines = trace(vec![1, 32768, 0, 0], TraceFilter::default());
+        assert_eq!(
+            lines[0],
+            r#"{"step":0,"ir":0,"op":"set","args":[32768,0],"vals":[0,0],"reg":[0,0]}"#
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        // noop x 10, halt
+        let mut words = vec![21; 10];
+        words.push(0);
+
+        let filter = TraceFilter {
+            range: Some((2, 8)),
+            from_step: 4,
+            limit: Some(3),
+        };
+        let lines = trace(words, filter);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(r#"{"step":4,"ir":4,"#));
+        assert!(lines[2].starts_with(r#"{"step":6,"ir":6,"#));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(TraceFilter::parse_range("6049-6089"), Some((6049, 6089)));
+        assert_eq!(TraceFilter::parse_range("6089-6049"), None);
+        assert_eq!(TraceFilter::parse_range("6049"), None);
+    }
+}
@@ -0,0 +1,632 @@
+//! Translation of a VM function into a register-machine IR, executed much faster than the VM.
+//!
+//! The function is split into basic blocks, from its entry address and following jumps and
+//! calls. Operands are resolved to registers or constants once, blocks are linked by index,
+//! and calls use the VM stack like the real code does, so deep recursion like the teleporter
+//! check (6049) runs in a loop rather than on the Rust stack.
+//!
+//! Only computation is supported: Input, output, halt and jumps to registers can't be
+//! translated. The code is assumed not to change while it runs: Writing to it is an error.
+//!
+//! Functions that don't access memory can be run with memoization: The result of each call
+//! only depends on the registers, so it is computed once per registers values. That's what
+//! makes the teleporter check, which is exponential, computable.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use super::error::{VmError, VmErrorKind};
+use super::instructions::{decode, Comparison, Ins, Instruction, Operation};
+use super::intreg::IntReg;
+use super::program::is_address;
+use super::register::Registers;
+use super::storage::{Memory, Storage};
+
+#[derive(Debug, PartialEq)]
+pub enum TranslateError {
+    Decode(VmError),
+    // Address and name of an instruction that can't be translated.
+    Unsupported(u16, &'static str),
+    // Address of a jump or call to a register.
+    IndirectJump(u16),
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranslateError::Decode(e) => write!(f, "{}", e),
+            TranslateError::Unsupported(a, name) => write!(f, "Cannot translate {} at {}", name, a),
+            TranslateError::IndirectJump(a) => write!(f, "Cannot translate indirect jump at {}", a),
+        }
+    }
+}
+
+impl std::error::Error for TranslateError {}
+
+#[derive(Debug, PartialEq)]
+pub enum ExecError {
+    Fault(VmError),
+    // Address written in the translated code.
+    CodeModified(u16),
+    // Address returned to that isn't after one of the translated calls.
+    UnknownReturn(u16),
+    // Number of instructions executed when the limit was reached.
+    StepLimit(u64),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::Fault(e) => write!(f, "{}", e),
+            ExecError::CodeModified(a) => write!(f, "Translated code modified at {}", a),
+            ExecError::UnknownReturn(a) => write!(f, "Return to unknown address {}", a),
+            ExecError::StepLimit(n) => write!(f, "Stopped after {} instructions", n),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Reg(usize),
+    Const(u16),
+}
+
+impl From<IntReg> for Operand {
+    fn from(x: IntReg) -> Self {
+        match x {
+            IntReg::Value(v) => Operand::Const(v),
+            IntReg::Register(r) => Operand::Reg(*r),
+        }
+    }
+}
+
+impl Operand {
+    #[inline]
+    fn get(self, regs: &[u16; 8]) -> u16 {
+        match self {
+            Operand::Reg(r) => regs[r],
+            Operand::Const(v) => v,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Set(usize, Operand),
+    Add(usize, Operand, Operand),
+    Mult(usize, Operand, Operand),
+    Mod(usize, Operand, Operand),
+    And(usize, Operand, Operand),
+    Or(usize, Operand, Operand),
+    Not(usize, Operand),
+    Eq(usize, Operand, Operand),
+    Gt(usize, Operand, Operand),
+    Push(Operand),
+    Pop(usize),
+    RMem(usize, Operand),
+    WMem(Operand, Operand),
+}
+
+// How a block ends. Targets are block indices.
+#[derive(Debug)]
+enum Exit {
+    Jump(usize),
+    // Jump to target if the operand is nonzero (jt) or zero (jf), else to next.
+    Branch {
+        cond: Operand,
+        if_true: bool,
+        target: usize,
+        next: usize,
+    },
+    // Pushes the return address and jumps to the target.
+    Call {
+        target: usize,
+        return_address: u16,
+        return_block: usize,
+    },
+    Ret,
+}
+
+#[derive(Debug)]
+struct Block {
+    // Operations with their address, for reporting faults. Noops are dropped.
+    ops: Vec<(u16, Op)>,
+    exit: Exit,
+    // Address of the instruction ending the block.
+    exit_address: u16,
+    // Number of VM instructions, including the noops and the exit.
+    len: u64,
+}
+
+// A translated function, that can be called on a storage.
+#[derive(Debug)]
+pub struct Function {
+    entry: u16,
+    // Index of the block at the entry, the code before it can be part of the function too.
+    entry_block: usize,
+    blocks: Vec<Block>,
+    // Return address and block of each call. Functions have few calls, so searching is faster
+    // than hashing.
+    return_sites: Vec<(u16, usize)>,
+    // Addresses of the words of the translated instructions.
+    code: BTreeSet<u16>,
+    // True if no instruction accesses memory, so calls can be memoized.
+    pure: bool,
+}
+
+// Registers after calls, by called block and registers before. The result of a call depends
+// only on the registers when the function doesn't access memory.
+pub type Memo = HashMap<(usize, [u16; 8]), [u16; 8]>;
+
+// A call made by the function that hasn't returned yet.
+struct CallFrame {
+    target: usize,
+    regs: [u16; 8],
+    return_address: u16,
+    // Stack length once the return address was pushed.
+    stack_base: usize,
+    // Set if the call used its caller's part of the stack, so it doesn't only depend on the
+    // registers.
+    tainted: bool,
+}
+
+// Instructions reachable from the entry, with the addresses that start a block.
+fn explore(
+    mem: &Memory,
+    entry: u16,
+) -> Result<(BTreeMap<u16, u16>, BTreeSet<u16>), TranslateError> {
+    // Address to length of each instruction.
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::from([entry]);
+    let mut to_visit = vec![entry];
+
+    while let Some(address) = to_visit.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let ins = get_instruction_at(mem, address)?;
+        let next = address + ins.offset();
+        instructions.insert(address, ins.offset());
+
+        match ins {
+            Ins::In(_) | Ins::Out(_) | Ins::Halt(_) => {
+                return Err(TranslateError::Unsupported(address, ins.name()))
+            }
+            Ins::Jmp(_) | Ins::JumpIf(_) | Ins::Call(_) => {
+                let target = literal_target(address, &ins)?;
+                leaders.insert(target);
+                to_visit.push(target);
+                if !matches!(ins, Ins::Jmp(_)) {
+                    leaders.insert(next);
+                    to_visit.push(next);
+                }
+            }
+            Ins::Ret(_) => {}
+            Ins::Set(_)
+            | Ins::Push(_)
+            | Ins::Pop(_)
+            | Ins::CmpOp(_)
+            | Ins::BinaryOp(_)
+            | Ins::Not(_)
+            | Ins::RMem(_)
+            | Ins::WMem(_)
+            | Ins::Noop(_) => to_visit.push(next),
+        }
+    }
+    Ok((instructions, leaders))
+}
+
+// Target of a jump or call, which must be a literal.
+fn literal_target(address: u16, ins: &Ins) -> Result<u16, TranslateError> {
+    ins.jump_target()
+        .and_then(|t| t.literal())
+        .ok_or(TranslateError::IndirectJump(address))
+}
+
+fn get_instruction_at(mem: &Memory, address: u16) -> Result<Ins, TranslateError> {
+    if !is_address(address) {
+        let kind = VmErrorKind::InvalidAddress(address);
+        return Err(TranslateError::Decode(VmError::new(
+            address,
+            String::new(),
+            kind,
+        )));
+    }
+    decode(address, mem.ins_slice(address)).map_err(|kind| {
+        let words: Vec<_> = mem
+            .ins_slice(address)
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        TranslateError::Decode(VmError::new(address, words.join(" "), kind))
+    })
+}
+
+// Translates the function starting at the address.
+pub fn translate(mem: &Memory, entry: u16) -> Result<Function, TranslateError> {
+    let (instructions, leaders) = explore(mem, entry)?;
+    let block_index: HashMap<u16, usize> =
+        leaders.iter().enumerate().map(|(i, a)| (*a, i)).collect();
+
+    let mut blocks = Vec::new();
+    let mut return_sites = Vec::new();
+    for leader in &leaders {
+        let mut ops = Vec::new();
+        let mut len = 0;
+        let mut address = *leader;
+        let (exit, exit_address) = loop {
+            let ins = get_instruction_at(mem, address)?;
+            let next = address + ins.offset();
+            len += 1;
+            let op = match ins {
+                Ins::Set(i) => Op::Set(*i.a, i.b.into()),
+                Ins::BinaryOp(i) => {
+                    let (a, b, c) = (*i.a, i.b.into(), i.c.into());
+                    match i.op {
+                        Operation::Add => Op::Add(a, b, c),
+                        Operation::Mult => Op::Mult(a, b, c),
+                        Operation::Mod => Op::Mod(a, b, c),
+                        Operation::And => Op::And(a, b, c),
+                        Operation::Or => Op::Or(a, b, c),
+                    }
+                }
+                Ins::Not(i) => Op::Not(*i.a, i.b.into()),
+                Ins::CmpOp(i) => {
+                    let (a, b, c) = (*i.a, i.b.into(), i.c.into());
+                    match i.cmp {
+                        Comparison::Eq => Op::Eq(a, b, c),
+                        Comparison::Gt => Op::Gt(a, b, c),
+                    }
+                }
+                Ins::Push(i) => Op::Push(i.a.into()),
+                Ins::Pop(i) => Op::Pop(*i.a),
+                Ins::RMem(i) => Op::RMem(*i.a, i.b.into()),
+                Ins::WMem(i) => Op::WMem(i.a.into(), i.b.into()),
+                Ins::Noop(_) => {
+                    if block_index.contains_key(&next) {
+                        break (Exit::Jump(block_index[&next]), address);
+                    }
+                    address = next;
+                    continue;
+                }
+                Ins::Jmp(_) => {
+                    let target = literal_target(address, &ins)?;
+                    break (Exit::Jump(block_index[&target]), address);
+                }
+                Ins::JumpIf(i) => {
+                    let exit = Exit::Branch {
+                        cond: i.a.into(),
+                        if_true: i.if_true,
+                        target: block_index[&literal_target(address, &ins)?],
+                        next: block_index[&next],
+                    };
+                    break (exit, address);
+                }
+                Ins::Call(_) => {
+                    return_sites.push((next, block_index[&next]));
+                    let exit = Exit::Call {
+                        target: block_index[&literal_target(address, &ins)?],
+                        return_address: next,
+                        return_block: block_index[&next],
+                    };
+                    break (exit, address);
+                }
+                Ins::Ret(_) => break (Exit::Ret, address),
+                Ins::In(_) | Ins::Out(_) | Ins::Halt(_) => {
+                    return Err(TranslateError::Unsupported(address, ins.name()))
+                }
+            };
+            ops.push((address, op));
+            if block_index.contains_key(&next) {
+                break (Exit::Jump(block_index[&next]), address);
+            }
+            address = next;
+        };
+        blocks.push(Block {
+            ops,
+            exit,
+            exit_address,
+            len,
+        });
+    }
+
+    let code = instructions
+        .iter()
+        .flat_map(|(a, len)| *a..*a + len)
+        .collect();
+    let pure = blocks
+        .iter()
+        .flat_map(|b: &Block| &b.ops)
+        .all(|(_, op)| !matches!(op, Op::RMem(..) | Op::WMem(..)));
+    Ok(Function {
+        entry,
+        entry_block: block_index[&entry],
+        blocks,
+        return_sites,
+        code,
+        pure,
+    })
+}
+
+impl Function {
+    pub fn entry(&self) -> u16 {
+        self.entry
+    }
+
+    // Runs the function like `call <entry>` would, until it returns. Returns the number of VM
+    // instructions executed, including the final ret. Stops with an error after max_steps.
+    pub fn call(&self, storage: &mut Storage, max_steps: Option<u64>) -> Result<u64, ExecError> {
+        self.call_with_memo(storage, max_steps, None)
+    }
+
+    // Same as call, reusing and recording the results of the calls made by the function in the
+    // memo. The memo is ignored if the function accesses memory. Calls skipped thanks to the memo
+    // don't count in the steps.
+    pub fn call_memoized(
+        &self,
+        storage: &mut Storage,
+        max_steps: Option<u64>,
+        memo: &mut Memo,
+    ) -> Result<u64, ExecError> {
+        self.call_with_memo(storage, max_steps, Some(memo).filter(|_| self.pure))
+    }
+
+    // True if the function doesn't access memory, so its calls can be memoized.
+    pub fn is_pure(&self) -> bool {
+        self.pure
+    }
+
+    fn call_with_memo(
+        &self,
+        storage: &mut Storage,
+        max_steps: Option<u64>,
+        memo: Option<&mut Memo>,
+    ) -> Result<u64, ExecError> {
+        let mut regs = storage.regs.to_array();
+        let result = self.exec(
+            &mut regs,
+            &mut storage.mem,
+            &mut storage.stack,
+            max_steps,
+            memo,
+        );
+        storage.regs = Registers::from_array(regs);
+        result
+    }
+
+    fn fault(&self, mem: &Memory, address: u16, kind: VmErrorKind) -> ExecError {
+        let ins = decode(address, mem.ins_slice(address)).map(|i| i.decompile());
+        ExecError::Fault(VmError::new(address, ins.unwrap_or_default(), kind))
+    }
+
+    fn exec(
+        &self,
+        regs: &mut [u16; 8],
+        mem: &mut Memory,
+        stack: &mut Vec<u16>,
+        max_steps: Option<u64>,
+        mut memo: Option<&mut Memo>,
+    ) -> Result<u64, ExecError> {
+        let max_steps = max_steps.unwrap_or(u64::MAX);
+        let mut steps = 0;
+        let mut frames: Vec<CallFrame> = Vec::new();
+        let mut block = self.entry_block;
+
+        loop {
+            let b = &self.blocks[block];
+            if steps + b.len > max_steps {
+                return Err(ExecError::StepLimit(steps));
+            }
+            steps += b.len;
+
+            for (address, op) in &b.ops {
+                match *op {
+                    Op::Set(a, x) => regs[a] = x.get(regs),
+                    Op::Add(a, x, y) => {
+                        regs[a] = ((x.get(regs) as u32 + y.get(regs) as u32) % 32768) as u16
+                    }
+                    Op::Mult(a, x, y) => {
+                        regs[a] = ((x.get(regs) as u32 * y.get(regs) as u32) % 32768) as u16
+                    }
+                    Op::Mod(a, x, y) => {
+                        regs[a] = x
+                            .get(regs)
+                            .checked_rem(y.get(regs))
+                            .ok_or_else(|| self.fault(mem, *address, VmErrorKind::DivisionByZero))?
+                    }
+                    Op::And(a, x, y) => regs[a] = x.get(regs) & y.get(regs),
+                    Op::Or(a, x, y) => regs[a] = x.get(regs) | y.get(regs),
+                    Op::Not(a, x) => regs[a] = !x.get(regs) & 0x7FFF,
+                    Op::Eq(a, x, y) => regs[a] = (x.get(regs) == y.get(regs)) as u16,
+                    Op::Gt(a, x, y) => regs[a] = (x.get(regs) > y.get(regs)) as u16,
+                    Op::Push(x) => stack.push(x.get(regs)),
+                    Op::Pop(a) => {
+                        if frames.last().is_some_and(|f| stack.len() <= f.stack_base) {
+                            frames.iter_mut().for_each(|f| f.tainted = true);
+                        }
+                        regs[a] = stack
+                            .pop()
+                            .ok_or_else(|| self.fault(mem, *address, VmErrorKind::EmptyStack))?
+                    }
+                    Op::RMem(a, x) => {
+                        let source = x.get(regs);
+                        if !is_address(source) {
+                            let kind = VmErrorKind::InvalidAddress(source);
+                            return Err(self.fault(mem, *address, kind));
+                        }
+                        regs[a] = mem.read(source)
+                    }
+                    Op::WMem(x, y) => {
+                        let target = x.get(regs);
+                        if !is_address(target) {
+                            let kind = VmErrorKind::InvalidAddress(target);
+                            return Err(self.fault(mem, *address, kind));
+                        }
+                        if self.code.contains(&target) {
+                            return Err(ExecError::CodeModified(target));
+                        }
+                        mem.write(target, y.get(regs));
+                    }
+                }
+            }
+
+            block = match b.exit {
+                Exit::Jump(next) => next,
+                Exit::Branch {
+                    cond,
+                    if_true,
+                    target,
+                    next,
+                } => {
+                    if (cond.get(regs) != 0) == if_true {
+                        target
+                    } else {
+                        next
+                    }
+                }
+                Exit::Call {
+                    target,
+                    return_address,
+                    return_block,
+                } => {
+                    if let Some(result) = memo.as_ref().and_then(|m| m.get(&(target, *regs))) {
+                        *regs = *result;
+                        return_block
+                    } else {
+                        stack.push(return_address);
+                        frames.push(CallFrame {
+                            target,
+                            regs: *regs,
+                            return_address,
+                            stack_base: stack.len(),
+                            tainted: false,
+                        });
+                        target
+                    }
+                }
+                Exit::Ret => {
+                    let Some(frame) = frames.pop() else {
+                        return Ok(steps);
+                    };
+                    let address = stack
+                        .pop()
+                        .ok_or_else(|| self.fault(mem, b.exit_address, VmErrorKind::EmptyStack))?;
+                    if let Some(memo) = memo.as_mut() {
+                        if !frame.tainted && address == frame.return_address {
+                            memo.insert((frame.target, frame.regs), *regs);
+                        }
+                    }
+                    self.return_sites
+                        .iter()
+                        .find(|(a, _)| *a == address)
+                        .ok_or(ExecError::UnknownReturn(address))?
+                        .1
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::machine::{ExitReason, Vm};
+    use crate::vm::program::Program;
+    use crate::vm::register::RegNb;
+
+    fn storage(words: Vec<u16>) -> Storage {
+        Storage::from_program(&Program::from_words(words).unwrap())
+    }
+
+    #[test]
+    fn test_loop() {
+        // 0: set r0 0, 3: add r0 r0 r1, 7: add r1 r1 32767, 11: jt r1 3, 14: noop, 15: ret
+        // Sum of 1 to r1.
+        let words = vec![
+            1, 32768, 0, 9, 32768, 32768, 32769, 9, 32769, 32769, 32767, 7, 32769, 3, 21, 18,
+        ];
+        let mut st = storage(words);
+        st.regs.set(RegNb::new(1), 10);
+        let function = translate(&st.mem, 0).unwrap();
+        assert_eq!(function.call(&mut st, None), Ok(1 + 3 * 10 + 2));
+        assert_eq!(st.regs.get(RegNb::new(0)), 55);
+
+        st.regs.set(RegNb::new(1), 10);
+        assert_eq!(
+            function.call(&mut st, Some(20)),
+            Err(ExecError::StepLimit(19))
+        );
+    }
+
+    #[test]
+    fn test_entry_after_code() {
+        // 0: add r0 r0 1, 4: ret, 5: jmp 0
+        let mut st = storage(vec![9, 32768, 32768, 1, 18, 6, 0]);
+        let function = translate(&st.mem, 5).unwrap();
+        assert_eq!(function.call(&mut st, None), Ok(3));
+        assert_eq!(st.regs.get(RegNb::new(0)), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        // out 'a', ret
+        let st = storage(vec![19, 97, 18]);
+        assert_eq!(
+            translate(&st.mem, 0).unwrap_err(),
+            TranslateError::Unsupported(0, "out")
+        );
+        // set r0 5, jmp r0
+        let st = storage(vec![1, 32768, 5, 6, 32768]);
+        assert_eq!(
+            translate(&st.mem, 0).unwrap_err(),
+            TranslateError::IndirectJump(3)
+        );
+        // wmem 1 0, ret
+        let mut st = storage(vec![16, 1, 0, 18]);
+        let function = translate(&st.mem, 0).unwrap();
+        assert_eq!(
+            function.call(&mut st, None),
+            Err(ExecError::CodeModified(1))
+        );
+        // pop r0, ret
+        let mut st = storage(vec![3, 32768, 18]);
+        let function = translate(&st.mem, 0).unwrap();
+        assert!(
+            matches!(function.call(&mut st, None), Err(ExecError::Fault(e)) if e.kind == VmErrorKind::EmptyStack)
+        );
+    }
+
+    #[test]
+    fn test_teleporter_check() {
+        // The recursive check, on inputs small enough to run it in the VM too: Ackermann(3, 1)
+        // when r7 is 1.
+        let regs = [(0, 3), (1, 1), (7, 1)];
+        let program = Program::challenge().unwrap();
+
+        let mut vm = Vm::new(&program);
+        for (r, v) in regs {
+            vm.storage_mut().regs.set(RegNb::new(r), v);
+        }
+        // call 6049, halt
+        for (a, w) in [(32760, 17), (32761, 6049), (32762, 0)] {
+            vm.storage_mut().mem.write(a, w);
+        }
+        vm.set_ir(32760);
+        assert_eq!(vm.run_until_input(), ExitReason::Halted);
+
+        let mut st = Storage::from_program(&program);
+        for (r, v) in regs {
+            st.regs.set(RegNb::new(r), v);
+        }
+        let function = translate(&st.mem, 6049).unwrap();
+        let steps = function.call(&mut st, None).unwrap();
+
+        assert_eq!(st.regs.get(RegNb::new(0)), 13);
+        assert_eq!(st.regs.to_array(), vm.storage().regs.to_array());
+        // Without the call and the halt.
+        assert_eq!(steps, vm.steps() - 2);
+        assert!(st.stack.is_empty());
+    }
+}